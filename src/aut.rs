@@ -1,12 +1,23 @@
+use crate::elim;
 use crate::expr::Expr;
+use crate::fields::Fields;
+use crate::minimize;
+use crate::omega::OmegaAut;
+use crate::prune;
+use crate::sp::SP;
 use crate::spp;
+use crate::trace::{Lasso, Packet, Trace};
+use crate::unproj;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::hash::Hash;
+use std::rc::Rc;
 // An AExpr represents an automaton state.
 // This is essentially a compressed and hash-consed form of a NetKAT expression.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum AExpr {
+pub enum AExpr {
     SPP(spp::SPP), // We keep field tests and mutations and combinations thereof in SPP form
     Union(Vec<State>), // e1 + e2 + ... + en
     Intersect(Vec<State>), // e1 & e2 & ... & en
@@ -18,11 +29,67 @@ enum AExpr {
     Dup,           // dup
     LtlNext(State), // X e
     LtlUntil(State, State), // e1 U e2
+    LtlRelease(State, State), // e1 R e2, only created by the negation normal form of omega mode
+    LtlYesterday(State), // Y e
+    LtlSince(State, State), // e1 S e2
+    // A state with past operators, together with the memory of each past operator it contains
+    // (sorted by operator). The memory of `Y e` is what `e` accepted one position earlier, advanced
+    // by one step, and the memory of `e1 S e2` is what `e1 S e2` accepted one position earlier,
+    // advanced by one step.
+    History(State, Vec<(State, State)>),
+    // rev e: the strings of e reversed, read back to front by a subset construction on the
+    // automaton of e. RevStates(e, states) has read a reversed suffix of a string, and `states`
+    // (sorted) are the states of e that accept this suffix.
+    Rev(State),
+    RevStates(State, Vec<State>),
     Top,           // represents the set of all strings
+    // proj {fields} e, where the hidden bits of the first packet are restricted to the SP
+    Proj(Vec<u32>, State, SP),
 }
 
 // A State is an index into the Aut's expression table.
-type State = usize;
+pub type State = usize;
+
+// The transitions of an atom in omega mode: a guard and the atoms of the target conjunction
+type OmegaOptions = Vec<(spp::SPP, Vec<State>)>;
+
+// A visit of the emptiness and equivalence checks: the packets newly added to a state,
+// and the visit + SPP of the transition that produced them (None for the root).
+type Visit = (SP, Option<(usize, spp::SPP)>);
+
+// Union-find over the (state, packets) pairs explored by the equivalence check
+struct UnionFind {
+    index: HashMap<(State, SP), usize>,
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new() -> Self {
+        UnionFind {
+            index: HashMap::new(),
+            parent: vec![],
+        }
+    }
+
+    fn find(&mut self, key: (State, SP)) -> usize {
+        let mut i = *self.index.entry(key).or_insert_with(|| {
+            self.parent.push(self.parent.len());
+            self.parent.len() - 1
+        });
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    /// Merges the classes of `a` and `b`, returning false if they were already merged
+    fn union(&mut self, a: (State, SP), b: (State, SP)) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b;
+        a != b
+    }
+}
 
 /// Symbolic transitions ST<T>.           
 /// Symbolic transitions represent, for each T, a set of packet pairs that can transition to T. These are represented as a finite map from T to SPP's.
@@ -48,6 +115,36 @@ impl ST {
     }
 }
 
+/// The part of an automaton reachable from a root, with the states numbered 0, 1, ...
+/// in breadth-first order (the root is state 0).
+/// The transitions refer to these numbers rather than to the states of the `Aut`.
+#[derive(Debug, Clone)]
+pub struct ExplicitAut {
+    /// The `Aut` state of each explicit state
+    pub states: Vec<State>,
+    /// The expression of each state
+    pub labels: Vec<AExpr>,
+    pub transitions: Vec<ST>,
+    pub epsilons: Vec<spp::SPP>,
+    /// False if the state limit was hit; transitions to the unexplored states are then missing
+    pub complete: bool,
+}
+
+impl ExplicitAut {
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn num_transitions(&self) -> usize {
+        self.transitions.iter().map(|st| st.transitions.len()).sum()
+    }
+
+    /// Returns the explicit number of the given `Aut` state, if it was explored
+    pub fn index_of(&self, state: State) -> Option<usize> {
+        self.states.iter().position(|&s| s == state)
+    }
+}
+
 pub struct Aut {
     aexprs: Vec<AExpr>,
     aexpr_map: HashMap<AExpr, State>,
@@ -55,6 +152,8 @@ pub struct Aut {
     epsilon_map: HashMap<State, spp::SPP>,
     spp: spp::SPPstore,
     num_calls: u32,
+    fields: Fields, // Names of the packet fields, used for display only
+    reversed_map: HashMap<State, Rc<ExplicitAut>>, // The automata of the expressions under `rev`
 }
 
 impl Aut {
@@ -66,6 +165,8 @@ impl Aut {
             epsilon_map: HashMap::new(),
             spp: spp::SPPstore::new(num_vars),
             num_calls: 0,
+            fields: Fields::new(),
+            reversed_map: HashMap::new(),
         };
         aut
     }
@@ -379,13 +480,95 @@ impl Aut {
         self.intern(AExpr::LtlUntil(e1, e2))
     }
 
+    fn mk_release(&mut self, e1: State, e2: State) -> State {
+        self.intern(AExpr::LtlRelease(e1, e2))
+    }
+
+    // The state !(!e1 U !e2), which accepts the same strings as e1 R e2
+    fn release_dual(&mut self, e1: State, e2: State) -> State {
+        let not_e1 = self.mk_complement(e1);
+        let not_e2 = self.mk_complement(e2);
+        let until = self.mk_until(not_e1, not_e2);
+        self.mk_complement(until)
+    }
+
+    /// Projects `e` onto the bits `fields` (sorted), where the hidden bits of the first packet
+    /// can take the values in `hidden_sp` (an SP over the hidden bits only)
+    fn mk_proj(&mut self, fields: Vec<u32>, e: State, hidden_sp: SP) -> State {
+        let zero = self.mk_spp(self.spp.zero);
+        if e == zero || hidden_sp == self.spp.sp.zero {
+            return zero;
+        }
+        if self.hidden_vars(&fields).is_empty() {
+            return e;
+        }
+        self.intern(AExpr::Proj(fields, e, hidden_sp))
+    }
+
+    /// A state with past operators, whose memories are given by `memory` (see `AExpr::History`).
+    /// Operators without a memory have not seen any position yet.
+    /// The past operators at the current position are resolved right away,
+    /// so a state without past operators under `X` is returned as is.
+    fn mk_history(&mut self, e: State, memory: &HashMap<State, State>) -> State {
+        let zero = self.mk_spp(self.spp.zero);
+        let memory: HashMap<State, State> = self
+            .past_operators(e)
+            .into_iter()
+            .map(|op| (op, memory.get(&op).copied().unwrap_or(zero)))
+            .collect();
+        let e = self.past_resolve(e, &memory, &mut HashMap::new());
+        let memory: Vec<(State, State)> = self
+            .past_operators(e)
+            .into_iter()
+            .map(|op| (op, memory[&op]))
+            .collect();
+        if memory.is_empty() {
+            return e;
+        }
+        self.intern(AExpr::History(e, memory))
+    }
+
+    fn mk_rev(&mut self, e: State) -> State {
+        match self.get_expr(e) {
+            // Without dups the string is a single pair of packets
+            AExpr::SPP(spp) => {
+                let spp = *spp;
+                let flipped = self.spp.flip(spp);
+                self.mk_spp(flipped)
+            }
+            AExpr::Rev(inner) => *inner,
+            AExpr::Top => e,
+            _ => self.intern(AExpr::Rev(e)),
+        }
+    }
+
+    // The bits that are hidden by a projection onto `fields`
+    fn hidden_vars(&self, fields: &[u32]) -> Vec<u32> {
+        (0..self.spp.num_vars())
+            .filter(|var| !fields.contains(var))
+            .collect()
+    }
+
     // Helper to get the actual expression from an index
     fn get_expr(&self, id: State) -> &AExpr {
         &self.aexprs[id]
     }
 
+    /// Parses the first expression of `input` and returns its state, for the tests of the automaton modules
+    #[cfg(test)]
+    pub(crate) fn parse_state(&mut self, input: &str) -> State {
+        let exprs = crate::parser::parse_expressions(input).unwrap();
+        self.expr_to_state(&exprs[0])
+    }
+
     // Function to convert an external Expr to an internal AExp index
     pub fn expr_to_state(&mut self, expr: &Expr) -> State {
+        let state = self.expr_to_state_helper(expr);
+        // Past operators start out with nothing before the first position
+        self.mk_history(state, &HashMap::new())
+    }
+
+    fn expr_to_state_helper(&mut self, expr: &Expr) -> State {
         match expr {
             Expr::Zero => self.mk_spp(self.spp.zero),
             Expr::One => self.mk_spp(self.spp.one),
@@ -398,50 +581,91 @@ impl Aut {
                 let spp = self.spp.test(*field, *value);
                 self.mk_spp(spp)
             }
+            Expr::AssignInt(start, width, value) => {
+                let spp = self.spp.assign_int(*start, *width, *value);
+                self.mk_spp(spp)
+            }
+            Expr::TestInt(start, width, value) => {
+                let spp = self.spp.test_int(*start, *width, *value);
+                self.mk_spp(spp)
+            }
             Expr::Union(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1); // e1 is Box<Expr>, dereferences automatically
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1); // e1 is Box<Expr>, dereferences automatically
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.mk_union(aexp1, aexp2)
             }
             Expr::Intersect(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1);
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.mk_intersect(aexp1, aexp2)
             }
             Expr::Xor(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1);
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.mk_xor(aexp1, aexp2)
             }
             Expr::Difference(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1);
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.mk_difference(aexp1, aexp2)
             }
             Expr::Complement(e) => {
-                let aexp = self.expr_to_state(e);
+                let aexp = self.expr_to_state_helper(e);
                 self.mk_complement(aexp)
             }
             Expr::Sequence(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1);
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.mk_sequence(aexp1, aexp2)
             }
             Expr::Star(e) => {
-                let aexp = self.expr_to_state(e);
+                let aexp = self.expr_to_state_helper(e);
                 self.mk_star(aexp)
             }
             Expr::Dup => self.mk_dup(),
             Expr::LtlNext(e) => {
-                let aexp = self.expr_to_state(e);
+                let aexp = self.expr_to_state_helper(e);
                 self.intern(AExpr::LtlNext(aexp))
             }
             Expr::LtlUntil(e1, e2) => {
-                let aexp1 = self.expr_to_state(e1);
-                let aexp2 = self.expr_to_state(e2);
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
                 self.intern(AExpr::LtlUntil(aexp1, aexp2))
             }
+            Expr::LtlYesterday(e) => {
+                let aexp = self.expr_to_state_helper(e);
+                self.intern(AExpr::LtlYesterday(aexp))
+            }
+            Expr::LtlSince(e1, e2) => {
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
+                self.intern(AExpr::LtlSince(aexp1, aexp2))
+            }
+            // A string of <e1> e2 is a string of e1 whose output packet starts a string of e2,
+            // which is the sequence e1; e2, so it gets the derivatives of the sequence.
+            // [e1] e2 is its dual !<e1> !e2.
+            Expr::LdlDiamond(e1, e2) => {
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
+                self.mk_sequence(aexp1, aexp2)
+            }
+            Expr::LdlBox(e1, e2) => {
+                let aexp1 = self.expr_to_state_helper(e1);
+                let aexp2 = self.expr_to_state_helper(e2);
+                let not_aexp2 = self.mk_complement(aexp2);
+                let diamond = self.mk_sequence(aexp1, not_aexp2);
+                self.mk_complement(diamond)
+            }
+            Expr::Rev(e) => {
+                // The past operators of e look back from the start of its own strings
+                let aexp = self.expr_to_state(e);
+                self.mk_rev(aexp)
+            }
             Expr::End => self.mk_spp(self.spp.top),
+            Expr::Proj(fields, e) => {
+                let aexp = self.expr_to_state_helper(e);
+                self.mk_proj(fields.clone(), aexp, self.spp.sp.one)
+            }
         }
     }
 
@@ -626,6 +850,186 @@ impl Aut {
         result
     }
 
+    // --- Past operators ---
+
+    // The past operators in `e` (sorted), including those nested in other past operators
+    fn past_operators(&self, e: State) -> Vec<State> {
+        let mut seen = HashSet::new();
+        let mut result = BTreeSet::new();
+        let mut stack = vec![e];
+        while let Some(state) = stack.pop() {
+            if !seen.insert(state) {
+                continue;
+            }
+            match self.get_expr(state) {
+                AExpr::LtlYesterday(e1) => {
+                    result.insert(state);
+                    stack.push(*e1);
+                }
+                AExpr::LtlSince(e1, e2) => {
+                    result.insert(state);
+                    stack.extend([*e1, *e2]);
+                }
+                AExpr::Union(states) | AExpr::Intersect(states) => stack.extend(states),
+                AExpr::Xor(e1, e2)
+                | AExpr::Difference(e1, e2)
+                | AExpr::Sequence(e1, e2)
+                | AExpr::LtlUntil(e1, e2)
+                | AExpr::LtlRelease(e1, e2) => stack.extend([*e1, *e2]),
+                AExpr::Complement(e1) | AExpr::Star(e1) | AExpr::LtlNext(e1) | AExpr::Proj(_, e1, _) => {
+                    stack.push(*e1)
+                }
+                // A nested history or reversal keeps its own memory
+                AExpr::History(..)
+                | AExpr::Rev(_)
+                | AExpr::RevStates(..)
+                | AExpr::SPP(_)
+                | AExpr::Dup
+                | AExpr::Top => {}
+            }
+        }
+        result.into_iter().collect()
+    }
+
+    /// Replaces the past operators that `e` evaluates at the current position by what they accept
+    /// according to `memory`: `Y e1` by its memory, and `e1 S e2` by `e2 + (e1 & memory)`.
+    /// The operators under `X` are evaluated at later positions, so `e1 U e2` is unfolded to
+    /// `e2 + (e1 & X (e1 U e2))` first. The right operand of a sequence and the operands of
+    /// `*` and `proj` are not resolved; the parser rejects past operators there.
+    fn past_resolve(
+        &mut self,
+        e: State,
+        memory: &HashMap<State, State>,
+        memo: &mut HashMap<State, State>,
+    ) -> State {
+        if let Some(&result) = memo.get(&e) {
+            return result;
+        }
+        if self.past_operators(e).is_empty() {
+            return e;
+        }
+        let result = match self.get_expr(e).clone() {
+            AExpr::LtlYesterday(_) => self.past_resolve(memory[&e], memory, memo),
+            AExpr::LtlSince(e1, e2) => {
+                // e1 S e2 = e2 + (e1 & Y (e1 S e2))
+                let e1 = self.past_resolve(e1, memory, memo);
+                let e2 = self.past_resolve(e2, memory, memo);
+                let before = self.past_resolve(memory[&e], memory, memo);
+                let e1_before = self.mk_intersect(e1, before);
+                self.mk_union(e2, e1_before)
+            }
+            AExpr::Union(states) => {
+                let states = states.iter().map(|&s| self.past_resolve(s, memory, memo)).collect();
+                self.mk_union_n(states)
+            }
+            AExpr::Intersect(states) => {
+                let states = states.iter().map(|&s| self.past_resolve(s, memory, memo)).collect();
+                self.mk_intersect_n(states)
+            }
+            AExpr::Xor(e1, e2) => {
+                let e1 = self.past_resolve(e1, memory, memo);
+                let e2 = self.past_resolve(e2, memory, memo);
+                self.mk_xor(e1, e2)
+            }
+            AExpr::Difference(e1, e2) => {
+                let e1 = self.past_resolve(e1, memory, memo);
+                let e2 = self.past_resolve(e2, memory, memo);
+                self.mk_difference(e1, e2)
+            }
+            AExpr::Complement(e1) => {
+                let e1 = self.past_resolve(e1, memory, memo);
+                self.mk_complement(e1)
+            }
+            AExpr::Sequence(e1, e2) => {
+                let e1 = self.past_resolve(e1, memory, memo);
+                self.mk_sequence(e1, e2)
+            }
+            AExpr::LtlUntil(e1, e2) => {
+                // e1 U e2 = e2 + (e1 & X (e1 U e2))
+                let e1 = self.past_resolve(e1, memory, memo);
+                let e2 = self.past_resolve(e2, memory, memo);
+                let next = self.intern(AExpr::LtlNext(e));
+                let e1_next = self.mk_intersect(e1, next);
+                self.mk_union(e2, e1_next)
+            }
+            AExpr::LtlRelease(e1, e2) => {
+                // e1 R e2 = e2 & (e1 + X (e1 R e2))
+                let e1 = self.past_resolve(e1, memory, memo);
+                let e2 = self.past_resolve(e2, memory, memo);
+                let next = self.intern(AExpr::LtlNext(e));
+                let e1_next = self.mk_union(e1, next);
+                self.mk_intersect(e2, e1_next)
+            }
+            AExpr::SPP(_)
+            | AExpr::Star(_)
+            | AExpr::Dup
+            | AExpr::LtlNext(_)
+            | AExpr::Top
+            | AExpr::Proj(..)
+            | AExpr::History(..)
+            | AExpr::Rev(_)
+            | AExpr::RevStates(..) => e,
+        };
+        memo.insert(e, result);
+        result
+    }
+
+    // --- Reversal ---
+
+    // The explicit automaton of `e`, which `rev e` reads back to front
+    fn reversed_aut(&mut self, e: State) -> Rc<ExplicitAut> {
+        if let Some(aut) = self.reversed_map.get(&e) {
+            return aut.clone();
+        }
+        let aut = Rc::new(self.explore(e));
+        self.reversed_map.insert(e, aut.clone());
+        aut
+    }
+
+    /// The transitions of a reversed state, where a pair of packets moves to each state of `e`
+    /// whose (flipped) SPP in `relations` contains it. Every pair goes to the set of all such
+    /// states, so the guards are split by the states they reach.
+    fn st_reversed(&mut self, e: State, relations: Vec<(State, spp::SPP)>) -> ST {
+        let zero = self.spp.zero;
+        let mut parts = vec![(self.spp.top, vec![])];
+        for (state, spp) in relations {
+            let mut refined = vec![];
+            for (guard, states) in parts {
+                let inside = self.spp.intersect(guard, spp);
+                let outside = self.spp.difference(guard, spp);
+                if inside != zero {
+                    refined.push((inside, [states.clone(), vec![state]].concat()));
+                }
+                if outside != zero {
+                    refined.push((outside, states));
+                }
+            }
+            parts = refined;
+        }
+        let mut result = ST::empty();
+        for (guard, mut states) in parts {
+            if states.is_empty() {
+                continue;
+            }
+            states.sort();
+            let target = self.intern(AExpr::RevStates(e, states));
+            self.st_insert(&mut result, target, guard);
+        }
+        result
+    }
+
+    // The union of the flipped transitions from explicit state `from` of `aut` into `states`
+    fn reversed_into(&mut self, aut: &ExplicitAut, from: usize, states: &[State]) -> spp::SPP {
+        let mut result = self.spp.zero;
+        for (&target, &spp) in aut.transitions[from].get_transitions() {
+            if states.contains(&aut.states[target]) {
+                let flipped = self.spp.flip(spp);
+                result = self.spp.union(result, flipped);
+            }
+        }
+        result
+    }
+
     // --- Automaton construction: delta, epsilon ---
 
     pub fn delta(&mut self, state: State) -> ST {
@@ -697,6 +1101,7 @@ impl Aut {
             }
             AExpr::Sequence(e1, e2) => {
                 // delta(e1 e2) = delta(e1) e2 + epsilon(e1) delta(e2)
+                // This is also the derivative of the LDL diamond <e1> e2
                 let epsilon_e1 = self.epsilon(e1);
                 let delta_e1 = self.delta(e1);
                 let delta_e2 = self.delta(e2);
@@ -725,10 +1130,97 @@ impl Aut {
                 let delta_e1_intersect_e1_u_e2 = self.st_intersect_expr(delta_e1, e1_u_e2);
                 self.st_union(delta_e2, delta_e1_intersect_e1_u_e2)
             }
+            AExpr::LtlRelease(e1, e2) => {
+                // delta(e1 R e2) = delta(!(!e1 U !e2))
+                let dual = self.release_dual(e1, e2);
+                self.delta(dual)
+            }
+            // A past operator outside of a history is at the first position
+            AExpr::LtlYesterday(_) => ST::empty(),
+            AExpr::LtlSince(_e1, e2) => self.delta(e2),
+            AExpr::History(e, memory) => {
+                // `e` is resolved already. Each transition is split by the target of what every
+                // past operator accepts at the current position, which becomes its new memory.
+                let mut memo = HashMap::new();
+                let memory_map: HashMap<State, State> = memory.iter().copied().collect();
+                let zero = self.spp.zero;
+                let mut steps: Vec<(spp::SPP, State, HashMap<State, State>)> = self
+                    .delta(e)
+                    .transitions
+                    .into_iter()
+                    .map(|(target, spp)| (spp, target, HashMap::new()))
+                    .collect();
+                for (op, _) in memory {
+                    let now = match self.get_expr(op).clone() {
+                        AExpr::LtlYesterday(e1) => self.past_resolve(e1, &memory_map, &mut memo),
+                        _ => self.past_resolve(op, &memory_map, &mut memo),
+                    };
+                    let delta_now = self.delta(now);
+                    let mut refined = vec![];
+                    for (spp, target, new_memory) in steps {
+                        let mut rest = spp;
+                        for (&remembered, &spp2) in &delta_now.transitions {
+                            let both = self.spp.intersect(spp, spp2);
+                            if both != zero {
+                                let mut new_memory = new_memory.clone();
+                                new_memory.insert(op, remembered);
+                                refined.push((both, target, new_memory));
+                                rest = self.spp.difference(rest, both);
+                            }
+                        }
+                        // Without a transition the memory is 0
+                        if rest != zero {
+                            refined.push((rest, target, new_memory));
+                        }
+                    }
+                    steps = refined;
+                }
+                let mut result = ST::empty();
+                for (spp, target, new_memory) in steps {
+                    let target = self.mk_history(target, &new_memory);
+                    self.st_insert(&mut result, target, spp);
+                }
+                result
+            }
+            AExpr::Rev(e) => {
+                // The first pair of a reversed string is the flipped epsilon pair of the original
+                let aut = self.reversed_aut(e);
+                let mut relations = vec![];
+                for (i, &epsilon) in aut.epsilons.iter().enumerate() {
+                    relations.push((aut.states[i], self.spp.flip(epsilon)));
+                }
+                self.st_reversed(e, relations)
+            }
+            AExpr::RevStates(e, states) => {
+                // Step back along the flipped transitions into the states that accept the suffix
+                let aut = self.reversed_aut(e);
+                let mut relations = vec![];
+                for i in 0..aut.num_states() {
+                    let spp = self.reversed_into(&aut, i, &states);
+                    relations.push((aut.states[i], spp));
+                }
+                self.st_reversed(e, relations)
+            }
             AExpr::Top => {
                 let top = self.mk_top();
                 self.st_singleton(self.spp.top, top)
             }
+            AExpr::Proj(fields, e, hidden_sp) => {
+                // Each transition of e is split by the hidden values its outputs can take,
+                // which become the hidden values of the first packet of the target
+                let hidden = self.hidden_vars(&fields);
+                let inputs = self.spp.ibwd(hidden_sp);
+                let delta_e = self.delta(e);
+                let mut result = ST::empty();
+                for (e2, spp) in delta_e.transitions {
+                    let spp = self.spp.intersect(spp, inputs);
+                    for (guard, outputs) in self.spp.split_hidden(spp, &hidden) {
+                        let target = self.mk_proj(fields.clone(), e2, outputs);
+                        self.st_insert(&mut result, target, guard);
+                    }
+                }
+                result
+            }
         };
 
         // Cache the result
@@ -802,7 +1294,30 @@ impl Aut {
             AExpr::Dup => self.spp.zero,
             AExpr::LtlNext(_) => self.spp.zero,
             AExpr::LtlUntil(_e1, e2) => self.epsilon(e2),
+            AExpr::LtlRelease(e1, e2) => {
+                let dual = self.release_dual(e1, e2);
+                self.epsilon(dual)
+            }
+            AExpr::LtlYesterday(_) => self.spp.zero,
+            AExpr::LtlSince(_e1, e2) => self.epsilon(e2),
+            AExpr::History(e, _) => self.epsilon(e),
+            AExpr::Rev(e) => {
+                let eps = self.epsilon(e);
+                self.spp.flip(eps)
+            }
+            AExpr::RevStates(e, states) => {
+                // The last pair of a reversed string is the flipped first transition of the original
+                let aut = self.reversed_aut(e);
+                self.reversed_into(&aut, 0, &states)
+            }
             AExpr::Top => self.spp.top,
+            AExpr::Proj(fields, e, hidden_sp) => {
+                let hidden = self.hidden_vars(&fields);
+                let eps = self.epsilon(e);
+                let inputs = self.spp.ibwd(hidden_sp);
+                let eps = self.spp.intersect(eps, inputs);
+                self.spp.exists(eps, &hidden)
+            }
         };
 
         // Cache the result
@@ -810,44 +1325,780 @@ impl Aut {
         result
     }
 
+    // --- Omega mode ---
+
+    /// Negation normal form for omega mode, of `!state` if `negate`: pushes the complements
+    /// through the boolean and LTL operators. A complement of a NetKAT expression stays in place.
+    /// An SPP, `end` and `dup` have no infinite strings, so their complement is `T`.
+    fn omega_nnf(
+        &mut self,
+        state: State,
+        negate: bool,
+        memo: &mut HashMap<(State, bool), State>,
+    ) -> State {
+        if let Some(&result) = memo.get(&(state, negate)) {
+            return result;
+        }
+        let expr = self.get_expr(state).clone();
+        let result = match (expr, negate) {
+            (AExpr::Union(states), false) | (AExpr::Intersect(states), true) => {
+                let states = states.iter().map(|&s| self.omega_nnf(s, negate, memo)).collect();
+                self.mk_union_n(states)
+            }
+            (AExpr::Intersect(states), false) | (AExpr::Union(states), true) => {
+                let states = states.iter().map(|&s| self.omega_nnf(s, negate, memo)).collect();
+                self.mk_intersect_n(states)
+            }
+            (AExpr::Xor(e1, e2), _) => {
+                // e1 ^ e2 = (e1 & !e2) + (!e1 & e2), !(e1 ^ e2) = (e1 & e2) + (!e1 & !e2)
+                let pos1 = self.omega_nnf(e1, false, memo);
+                let neg1 = self.omega_nnf(e1, true, memo);
+                let pos2 = self.omega_nnf(e2, false, memo);
+                let neg2 = self.omega_nnf(e2, true, memo);
+                let (a, b) = if negate { (pos2, neg2) } else { (neg2, pos2) };
+                let left = self.mk_intersect(pos1, a);
+                let right = self.mk_intersect(neg1, b);
+                self.mk_union(left, right)
+            }
+            (AExpr::Difference(e1, e2), false) => {
+                let pos1 = self.omega_nnf(e1, false, memo);
+                let neg2 = self.omega_nnf(e2, true, memo);
+                self.mk_intersect(pos1, neg2)
+            }
+            (AExpr::Difference(e1, e2), true) => {
+                let neg1 = self.omega_nnf(e1, true, memo);
+                let pos2 = self.omega_nnf(e2, false, memo);
+                self.mk_union(neg1, pos2)
+            }
+            (AExpr::Complement(e), _) => self.omega_nnf(e, !negate, memo),
+            (AExpr::LtlNext(e), _) => {
+                let e = self.omega_nnf(e, negate, memo);
+                self.intern(AExpr::LtlNext(e))
+            }
+            (AExpr::LtlUntil(e1, e2), _) => {
+                let e1 = self.omega_nnf(e1, negate, memo);
+                let e2 = self.omega_nnf(e2, negate, memo);
+                if negate { self.mk_release(e1, e2) } else { self.mk_until(e1, e2) }
+            }
+            (AExpr::LtlRelease(e1, e2), _) => {
+                let e1 = self.omega_nnf(e1, negate, memo);
+                let e2 = self.omega_nnf(e2, negate, memo);
+                if negate { self.mk_until(e1, e2) } else { self.mk_release(e1, e2) }
+            }
+            (AExpr::History(..), _) => panic!("Past operators are not supported in omega mode"),
+            (AExpr::Top, true) => self.mk_spp(self.spp.zero),
+            (AExpr::SPP(_) | AExpr::Dup, true) => self.mk_top(),
+            (_, true) => self.mk_complement(state),
+            (_, false) => state,
+        };
+        memo.insert((state, negate), result);
+        result
+    }
+
+    /// The disjunctive normal form of a state in omega mode: a list of conjunctions of atoms
+    fn omega_dnf(&self, state: State) -> Vec<Vec<State>> {
+        match self.get_expr(state) {
+            AExpr::Top => vec![vec![]],
+            AExpr::SPP(spp) if *spp == self.spp.zero => vec![],
+            AExpr::Union(states) => states.iter().flat_map(|&s| self.omega_dnf(s)).collect(),
+            AExpr::Intersect(states) => {
+                let mut result = vec![vec![]];
+                for &s in states {
+                    let mut product = vec![];
+                    for conj in &result {
+                        for conj2 in self.omega_dnf(s) {
+                            let mut conj: Vec<State> = conj.clone();
+                            conj.extend(conj2);
+                            conj.sort();
+                            conj.dedup();
+                            product.push(conj);
+                        }
+                    }
+                    result = product;
+                }
+                result
+            }
+            _ => vec![vec![state]],
+        }
+    }
+
+    /// Whether a run may not stay in the atom forever: an until, or a NetKAT expression
+    /// that is not a complement (see the `omega` module)
+    fn is_omega_obligation(&self, atom: State) -> bool {
+        !matches!(
+            self.get_expr(atom),
+            AExpr::Complement(_) | AExpr::LtlRelease(..) | AExpr::LtlNext(_) | AExpr::Top
+        )
+    }
+
+    /// The transitions of an atom in omega mode
+    fn omega_options(
+        &mut self,
+        atom: State,
+        nnf_memo: &mut HashMap<(State, bool), State>,
+        memo: &mut HashMap<State, OmegaOptions>,
+    ) -> OmegaOptions {
+        if let Some(result) = memo.get(&atom) {
+            return result.clone();
+        }
+        let result = match self.get_expr(atom).clone() {
+            AExpr::LtlNext(e) => {
+                let conjs = self.omega_dnf(e);
+                conjs.into_iter().map(|conj| (self.spp.top, conj)).collect()
+            }
+            AExpr::LtlUntil(e1, e2) => {
+                // e1 U e2 = e2 + (e1 & X (e1 U e2))
+                let mut result = self.omega_state_options(e2, nnf_memo, memo);
+                for (guard, mut conj) in self.omega_state_options(e1, nnf_memo, memo) {
+                    conj.push(atom);
+                    result.push((guard, conj));
+                }
+                result
+            }
+            AExpr::LtlRelease(e1, e2) => {
+                // e1 R e2 = e2 & (e1 + X (e1 R e2))
+                let options1 = self.omega_state_options(e1, nnf_memo, memo);
+                let mut result = vec![];
+                for (guard2, conj2) in self.omega_state_options(e2, nnf_memo, memo) {
+                    for (guard1, conj1) in &options1 {
+                        let guard = self.spp.intersect(*guard1, guard2);
+                        result.push((guard, [conj1.clone(), conj2.clone()].concat()));
+                    }
+                    result.push((guard2, [conj2, vec![atom]].concat()));
+                }
+                result
+            }
+            _ => {
+                // A NetKAT expression: its targets are put in normal form again
+                let mut result = vec![];
+                for (target, guard) in self.delta(atom).transitions {
+                    let target = self.omega_nnf(target, false, nnf_memo);
+                    for conj in self.omega_dnf(target) {
+                        result.push((guard, conj));
+                    }
+                }
+                result
+            }
+        };
+        let result: OmegaOptions = result
+            .into_iter()
+            .filter(|&(guard, _)| guard != self.spp.zero)
+            .map(|(guard, mut conj)| {
+                conj.sort();
+                conj.dedup();
+                (guard, conj)
+            })
+            .collect();
+        memo.insert(atom, result.clone());
+        result
+    }
+
+    /// The transitions of a conjunction of atoms, with the target atoms of each atom
+    fn omega_conj_options(
+        &mut self,
+        conj: &[State],
+        nnf_memo: &mut HashMap<(State, bool), State>,
+        memo: &mut HashMap<State, OmegaOptions>,
+    ) -> Vec<(spp::SPP, Vec<Vec<State>>)> {
+        let mut result = vec![(self.spp.top, vec![])];
+        for &atom in conj {
+            let options = self.omega_options(atom, nnf_memo, memo);
+            let mut product = vec![];
+            for (guard, targets) in &result {
+                for (guard2, conj2) in &options {
+                    let guard = self.spp.intersect(*guard, *guard2);
+                    if guard != self.spp.zero {
+                        product.push((guard, [targets.clone(), vec![conj2.clone()]].concat()));
+                    }
+                }
+            }
+            result = product;
+        }
+        result
+    }
+
+    /// The transitions of an arbitrary state in omega mode
+    fn omega_state_options(
+        &mut self,
+        state: State,
+        nnf_memo: &mut HashMap<(State, bool), State>,
+        memo: &mut HashMap<State, OmegaOptions>,
+    ) -> OmegaOptions {
+        let mut result = vec![];
+        for conj in self.omega_dnf(state) {
+            for (guard, targets) in self.omega_conj_options(&conj, nnf_memo, memo) {
+                result.push((guard, targets.concat()));
+            }
+        }
+        result
+    }
+
+    /// Materializes the omega automaton of `root`, see the `omega` module
+    pub fn explore_omega(&mut self, root: State) -> OmegaAut {
+        let mut nnf_memo = HashMap::new();
+        let mut memo = HashMap::new();
+        let root = self.omega_nnf(root, false, &mut nnf_memo);
+        let mut omega = OmegaAut {
+            states: vec![],
+            initial: vec![],
+            transitions: vec![],
+            accepting: vec![],
+        };
+        let mut index: HashMap<(Vec<State>, Vec<State>), usize> = HashMap::new();
+        let mut lookup = |omega: &mut OmegaAut, key: (Vec<State>, Vec<State>)| {
+            *index.entry(key.clone()).or_insert_with(|| {
+                omega.states.push(key);
+                omega.states.len() - 1
+            })
+        };
+        for conj in self.omega_dnf(root) {
+            let initial = lookup(&mut omega, (conj, vec![]));
+            if !omega.initial.contains(&initial) {
+                omega.initial.push(initial);
+            }
+        }
+        let mut i = 0;
+        while i < omega.states.len() {
+            let (conj, waiting) = omega.states[i].clone();
+            omega.accepting.push(waiting.is_empty());
+            let mut transitions: HashMap<usize, spp::SPP> = HashMap::new();
+            for (guard, targets) in self.omega_conj_options(&conj, &mut nnf_memo, &mut memo) {
+                let mut next = targets.concat();
+                next.sort();
+                next.dedup();
+                // The breakpoint: follow the waiting obligations, or start over from all of them
+                let mut next_waiting: Vec<State> = if waiting.is_empty() {
+                    next.clone()
+                } else {
+                    conj.iter()
+                        .zip(&targets)
+                        .filter(|(atom, _)| waiting.contains(atom))
+                        .flat_map(|(_, target)| target.clone())
+                        .collect()
+                };
+                next_waiting.retain(|&atom| self.is_omega_obligation(atom));
+                next_waiting.sort();
+                next_waiting.dedup();
+                let target = lookup(&mut omega, (next, next_waiting));
+                let entry = transitions.entry(target).or_insert(self.spp.zero);
+                *entry = self.spp.union(*entry, guard);
+            }
+            let mut transitions: Vec<(usize, spp::SPP)> = transitions.into_iter().collect();
+            transitions.sort();
+            omega.transitions.push(transitions);
+            i += 1;
+        }
+        omega
+    }
+
+    /// Checks whether the given state accepts no infinite string
+    pub fn omega_is_empty(&mut self, state: State) -> bool {
+        let omega = self.explore_omega(state);
+        omega.is_empty(&mut self.spp)
+    }
+
+    /// Returns an infinite string accepted by the given state, or `None` if there is none
+    pub fn omega_witness(&mut self, state: State) -> Option<Lasso> {
+        let omega = self.explore_omega(state);
+        omega.lasso(&mut self.spp)
+    }
+
+    /// Checks whether the given state accepts the infinite string of the lasso
+    pub fn omega_accepts(&mut self, state: State, lasso: &Lasso) -> bool {
+        let omega = self.explore_omega(state);
+        omega.accepts(&self.spp, lasso)
+    }
+
+    /// Returns an infinite string accepted by exactly one of the two states,
+    /// or `None` if they accept the same infinite strings
+    pub fn omega_equiv_counterexample(&mut self, s1: State, s2: State) -> Option<Lasso> {
+        let xor = self.mk_xor(s1, s2);
+        self.omega_witness(xor)
+    }
+
+    /// Returns an infinite string accepted by `s1` but not by `s2`, or `None` if there is none
+    pub fn omega_includes_counterexample(&mut self, s1: State, s2: State) -> Option<Lasso> {
+        let difference = self.mk_difference(s1, s2);
+        self.omega_witness(difference)
+    }
+
     /// Returns a reference to the internal SPPstore
     pub fn spp_store(&self) -> &spp::SPPstore {
         &self.spp
     }
 
+    pub fn spp_store_mut(&mut self) -> &mut spp::SPPstore {
+        &mut self.spp
+    }
+
+    /// Sets the field layout used to display packets of this automaton
+    pub fn set_fields(&mut self, fields: Fields) {
+        self.fields = fields;
+    }
+
+    pub fn fields(&self) -> &Fields {
+        &self.fields
+    }
+
+    /// Garbage collects the SPP store, keeping only the SPPs referenced by the states
+    /// and by the cached transitions and epsilons.
+    /// States stay valid, but SPPs obtained from the automaton before the collection do not.
+    pub fn gc(&mut self) {
+        let mut roots = vec![];
+        let mut sp_roots = vec![];
+        for expr in &self.aexprs {
+            match expr {
+                AExpr::SPP(spp) => roots.push(*spp),
+                AExpr::Proj(_, _, hidden_sp) => sp_roots.push(*hidden_sp),
+                _ => {}
+            }
+        }
+        for st in self.delta_map.values() {
+            roots.extend(st.transitions.values().copied());
+        }
+        roots.extend(self.epsilon_map.values().copied());
+
+        let (remap, sp_remap) = self.spp.gc(&roots, &sp_roots);
+        for expr in &mut self.aexprs {
+            match expr {
+                AExpr::SPP(spp) => *spp = remap[spp],
+                AExpr::Proj(_, _, hidden_sp) => *hidden_sp = sp_remap[hidden_sp],
+                _ => {}
+            }
+        }
+        self.aexpr_map = self
+            .aexprs
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(state, expr)| (expr, state))
+            .collect();
+        for st in self.delta_map.values_mut() {
+            for spp in st.transitions.values_mut() {
+                *spp = remap[spp];
+            }
+        }
+        for spp in self.epsilon_map.values_mut() {
+            *spp = remap[spp];
+        }
+        // The reversed automata are explored again when needed
+        self.reversed_map.clear();
+    }
+
+    /// Materializes the states reachable from `root`, see `ExplicitAut`
+    pub fn explore(&mut self, root: State) -> ExplicitAut {
+        self.explore_with_limit(root, usize::MAX)
+    }
+
+    /// Materializes at most `max_states` of the states reachable from `root`
+    pub fn explore_with_limit(&mut self, root: State, max_states: usize) -> ExplicitAut {
+        let mut index: HashMap<State, usize> = HashMap::new();
+        let mut states = vec![];
+        let mut complete = true;
+        if max_states > 0 {
+            index.insert(root, 0);
+            states.push(root);
+        } else {
+            complete = false;
+        }
+        let mut transitions = vec![];
+        let mut epsilons = vec![];
+        let mut i = 0;
+        while i < states.len() {
+            let state = states[i];
+            epsilons.push(self.epsilon(state));
+            let mut explicit = HashMap::new();
+            for (target, spp) in self.delta(state).transitions {
+                let target = match index.get(&target) {
+                    Some(&target) => target,
+                    None if states.len() < max_states => {
+                        index.insert(target, states.len());
+                        states.push(target);
+                        states.len() - 1
+                    }
+                    None => {
+                        complete = false;
+                        continue;
+                    }
+                };
+                explicit.insert(target, spp);
+            }
+            transitions.push(ST::new(explicit));
+            i += 1;
+        }
+        let labels = states
+            .iter()
+            .map(|&state| self.get_expr(state).clone())
+            .collect();
+        ExplicitAut {
+            states,
+            labels,
+            transitions,
+            epsilons,
+            complete,
+        }
+    }
+
+    /// Materializes the states reachable from `root` and merges the states with the same
+    /// symbolic behaviour on their reachable packets, see `minimize::minimize`
+    pub fn minimize(&mut self, root: State) -> ExplicitAut {
+        let explicit = self.explore(root);
+        minimize::minimize(&explicit, &mut self.spp)
+    }
+
+    /// Materializes the states reachable from `root`, keeping only the packet pairs
+    /// that occur in some accepted string, see `prune::prune`
+    pub fn prune(&mut self, root: State) -> ExplicitAut {
+        let explicit = self.explore(root);
+        prune::prune(&explicit, &mut self.spp)
+    }
+
+    /// Eliminates the dups of the given state: returns the SPP relating the first packet
+    /// of each accepted string to its output packet (Phi), see `elim::eliminate`
+    pub fn eliminate(&mut self, root: State) -> spp::SPP {
+        let explicit = self.explore(root);
+        elim::eliminate(&explicit, &mut self.spp)
+    }
+
+    /// Compiles the states reachable from `root` into extra packet bits, so that the
+    /// automaton becomes a dup-free step relation on the widened packets, see `unproj::unproject`
+    pub fn unproject(&mut self, root: State) -> unproj::Unprojection {
+        let explicit = self.explore(root);
+        unproj::unproject(&explicit, &self.spp)
+    }
+
     /// Checks if the given state is empty
     pub fn is_empty(&mut self, state: State) -> bool {
+        self.witness(state).is_none()
+    }
+
+    /// Returns a concrete string accepted by the given state, or `None` if the state is empty
+    pub fn witness(&mut self, state: State) -> Option<Trace> {
+        let mut visits: Vec<Visit> = vec![];
         // Todo: list of states to visit
         // Note: One = Top for SPs
-        let mut todo = vec![(state, self.spp.sp.one)];
+        let mut todo = vec![(state, self.spp.sp.one, None)];
         // Hashmap of SPs for each state reachable from the given state
         let mut sp_map = HashMap::new();
-        while !todo.is_empty() {
-            let (state, sp) = todo.pop().unwrap();
+        while let Some((state, sp, parent)) = todo.pop() {
             // Union the SPP into the map
             let original_sp = sp_map.entry(state).or_insert(self.spp.sp.zero);
             let to_add = self.spp.sp.difference(sp, *original_sp);
-            if to_add != self.spp.sp.zero {
-                *original_sp = self.spp.sp.union(*original_sp, to_add);
-                // iterate over all transitions from the state
+            if to_add == self.spp.sp.zero {
+                continue;
+            }
+            *original_sp = self.spp.sp.union(*original_sp, to_add);
+            visits.push((to_add, parent));
+            let visit = visits.len() - 1;
+
+            // Check if the new packets, when composed with the epsilon of the state, are non-empty
+            let epsilon_spp: spp::SPP = self.epsilon(state);
+            let sp_composed = self.spp.push(to_add, epsilon_spp);
+            if sp_composed != self.spp.sp.zero {
+                return Some(self.reconstruct_trace(&visits, visit, epsilon_spp));
+            }
+
+            // iterate over all transitions from the state
+            for (state2, spp2) in self.delta(state).transitions {
+                // NB: `push(to_add, spp2) === naive_forward(to_add; spp2)`,
+                // where `;` is sequential composition
+                let seq_forward = self.spp.push(to_add, spp2);
+                todo.push((state2, seq_forward, Some((visit, spp2))));
+            }
+        }
+        None
+    }
+
+    /// Returns a shortest string accepted by the given state, or `None` if the state is empty.
+    /// Among the strings with the fewest dup steps, we return the lexicographically smallest one
+    /// (comparing packet by packet, and within a packet preferring 0 over 1 for x0, x1, ...).
+    pub fn shortest_witness(&mut self, state: State) -> Option<Trace> {
+        let root = state;
+        // Breadth-first search: each level contains the packets that first reach a state in that many steps
+        let mut seen: HashMap<State, SP> = HashMap::new();
+        let mut frontier: HashMap<State, SP> = HashMap::from([(root, self.spp.sp.one)]);
+        let mut depth = 0;
+        loop {
+            if frontier.is_empty() {
+                return None;
+            }
+            let mut accepting = false;
+            for (&state, &sp) in &frontier {
+                let original_sp = seen.entry(state).or_insert(self.spp.sp.zero);
+                *original_sp = self.spp.sp.union(*original_sp, sp);
+                let epsilon_spp = self.epsilon(state);
+                if self.spp.push(sp, epsilon_spp) != self.spp.sp.zero {
+                    accepting = true;
+                }
+            }
+            if accepting {
+                break;
+            }
+            let mut next: HashMap<State, SP> = HashMap::new();
+            for (state, sp) in frontier {
                 for (state2, spp2) in self.delta(state).transitions {
-                    // NB: `push(to_add, spp2) === naive_forward(to_add; spp2)`,
-                    // where `;` is sequential composition
-                    let seq_forward = self.spp.push(to_add, spp2);
-                    todo.push((state2, seq_forward));
+                    let image = self.spp.push(sp, spp2);
+                    let next_sp = next.entry(state2).or_insert(self.spp.sp.zero);
+                    *next_sp = self.spp.sp.union(*next_sp, image);
+                }
+            }
+            frontier = HashMap::new();
+            for (state, sp) in next {
+                let seen_sp = seen.get(&state).copied().unwrap_or(self.spp.sp.zero);
+                let new_sp = self.spp.sp.difference(sp, seen_sp);
+                if new_sp != self.spp.sp.zero {
+                    frontier.insert(state, new_sp);
                 }
             }
+            depth += 1;
         }
 
-        // Check if the SPPs in the map when composed with the epsilon of the given state are empty
-        for (state, sp) in sp_map {
-            let epsilon_spp: spp::SPP = self.epsilon(state);
-            let sp_composed = self.spp.push(sp, epsilon_spp);
-            if sp_composed != self.spp.sp.zero {
-                return false;
+        // Backward pass: can_accept[j][s] is the set of packets at state s
+        // from which some string with exactly j more steps is accepted.
+        // The accepting path found above only visits states in `seen`.
+        let mut can_accept: Vec<HashMap<State, SP>> = vec![];
+        let mut level = HashMap::new();
+        for &state in seen.keys() {
+            let epsilon_spp = self.epsilon(state);
+            level.insert(state, self.spp.pull(epsilon_spp, self.spp.sp.one));
+        }
+        can_accept.push(level);
+        for j in 1..=depth {
+            let mut level = HashMap::new();
+            for &state in seen.keys() {
+                let mut sp = self.spp.sp.zero;
+                for (state2, spp2) in self.delta(state).transitions {
+                    if let Some(&target_sp) = can_accept[j - 1].get(&state2) {
+                        let source_sp = self.spp.pull(spp2, target_sp);
+                        sp = self.spp.sp.union(sp, source_sp);
+                    }
+                }
+                level.insert(state, sp);
             }
+            can_accept.push(level);
         }
-        true
+
+        // Forward pass: greedily pick the smallest packet that can still be completed in time
+        let mut state = root;
+        let mut packet = self.spp.sp.pick(can_accept[depth][&root]).unwrap();
+        let mut packets = vec![];
+        for j in (1..=depth).rev() {
+            let packet_sp = self.spp.sp.singleton(&packet);
+            let mut best: Option<(Packet, State)> = None;
+            for (state2, spp2) in self.delta(state).transitions {
+                let Some(&target_sp) = can_accept[j - 1].get(&state2) else {
+                    continue;
+                };
+                let image = self.spp.push(packet_sp, spp2);
+                let candidates = self.spp.sp.intersect(image, target_sp);
+                if let Some(next) = self.spp.sp.pick(candidates)
+                    && best.as_ref().is_none_or(|(best_packet, _)| next < *best_packet)
+                {
+                    best = Some((next, state2));
+                }
+            }
+            let (next, state2) = best.unwrap();
+            packets.push(packet);
+            packet = next;
+            state = state2;
+        }
+        let packet_sp = self.spp.sp.singleton(&packet);
+        let epsilon_spp = self.epsilon(state);
+        let outputs = self.spp.push(packet_sp, epsilon_spp);
+        packets.push(packet);
+        packets.push(self.spp.sp.pick(outputs).unwrap());
+        Some(Trace::new(packets))
+    }
+
+    /// Walks back from an accepting visit to the root, picking concrete packets along the way.
+    /// The last packet and the output packet are picked from the `accept` SPP.
+    fn reconstruct_trace(&mut self, visits: &[Visit], visit: usize, accept: spp::SPP) -> Trace {
+        let (sp, mut parent) = visits[visit];
+        let accepting = self.spp.pull(accept, self.spp.sp.one);
+        let last = self.spp.sp.intersect(sp, accepting);
+        let last = self.spp.sp.pick(last).unwrap();
+        let last_sp = self.spp.sp.singleton(&last);
+        let outputs = self.spp.push(last_sp, accept);
+        let output = self.spp.sp.pick(outputs).unwrap();
+
+        // Walk back along the transitions, picking a predecessor for each packet
+        let mut packets: Vec<Packet> = vec![output, last];
+        while let Some((prev, spp)) = parent {
+            let (prev_sp, prev_parent) = visits[prev];
+            let next_sp = self.spp.sp.singleton(packets.last().unwrap());
+            let candidates = self.spp.pull(spp, next_sp);
+            let candidates = self.spp.sp.intersect(prev_sp, candidates);
+            packets.push(self.spp.sp.pick(candidates).unwrap());
+            parent = prev_parent;
+        }
+        packets.reverse();
+        Trace::new(packets)
+    }
+
+    /// Checks whether two states accept the same strings
+    pub fn equiv(&mut self, s1: State, s2: State) -> bool {
+        self.equiv_counterexample(s1, s2).is_none()
+    }
+
+    /// Returns a string accepted by exactly one of the two states, or `None` if they are equivalent.
+    ///
+    /// Explores pairs of states in the style of Hopcroft and Karp, without building the xor
+    /// automaton. Each state of a pair is restricted to the packets that can reach it, so the
+    /// pairs live in union-find as `(state, packets)` nodes, and a pair whose two sides are
+    /// already merged is skipped. The transitions of the two states are intersected pairwise;
+    /// packets for which only one side has a transition are paired with the empty state.
+    /// The search fails as soon as the epsilons of a pair differ on the reachable packets.
+    pub fn equiv_counterexample(&mut self, s1: State, s2: State) -> Option<Trace> {
+        let empty = self.mk_spp(self.spp.zero);
+        let mut classes = UnionFind::new();
+        let mut visits: Vec<Visit> = vec![];
+        let mut todo = VecDeque::from([(s1, s2, self.spp.sp.one, None)]);
+        while let Some((s1, s2, sp, parent)) = todo.pop_front() {
+            if !classes.union((s1, sp), (s2, sp)) {
+                continue;
+            }
+            visits.push((sp, parent));
+            let visit = visits.len() - 1;
+
+            // Fail fast if the epsilons differ on the packets that reach this pair
+            let (eps1, eps2) = (self.epsilon(s1), self.epsilon(s2));
+            let diff = self.spp.xor(eps1, eps2);
+            let reachable = self.spp.ibwd(sp);
+            let diff = self.spp.intersect(diff, reachable);
+            if diff != self.spp.zero {
+                return Some(self.reconstruct_trace(&visits, visit, diff));
+            }
+
+            let delta1 = self.delta(s1).transitions;
+            let delta2 = self.delta(s2).transitions;
+            let mut successors = vec![];
+            for (&t1, &spp1) in &delta1 {
+                let mut rest = spp1;
+                for (&t2, &spp2) in &delta2 {
+                    let both = self.spp.intersect(spp1, spp2);
+                    successors.push((t1, t2, both));
+                    rest = self.spp.difference(rest, spp2);
+                }
+                successors.push((t1, empty, rest));
+            }
+            for (&t2, &spp2) in &delta2 {
+                let mut rest = spp2;
+                for &spp1 in delta1.values() {
+                    rest = self.spp.difference(rest, spp1);
+                }
+                successors.push((empty, t2, rest));
+            }
+            for (t1, t2, spp) in successors {
+                let next_sp = self.spp.push(sp, spp);
+                if next_sp != self.spp.sp.zero {
+                    todo.push_back((t1, t2, next_sp, Some((visit, spp))));
+                }
+            }
+        }
+        None
+    }
+
+    /// Checks whether every string accepted by `s1` is accepted by `s2`
+    pub fn includes(&mut self, s1: State, s2: State) -> bool {
+        self.includes_counterexample(s1, s2).is_none()
+    }
+
+    /// Returns a string accepted by `s1` but not by `s2`, or `None` if `s1` is included in `s2`.
+    ///
+    /// Explores the product of `s1` with sets of states of `s2`, without complementing `s2`.
+    /// A node `(t1, set, packets)` is subsumed by a visited node `(t1, set', packets')` with
+    /// `set' ⊆ set`, since any counterexample from the former is one from the latter.
+    /// Subsumption is checked per packet: only the packets not covered by such a visited
+    /// node are explored further, so the visited nodes form a symbolic antichain.
+    pub fn includes_counterexample(&mut self, s1: State, s2: State) -> Option<Trace> {
+        let mut visits: Vec<Visit> = vec![];
+        let mut antichain: HashMap<State, Vec<(BTreeSet<State>, SP)>> = HashMap::new();
+        let mut todo = VecDeque::from([(s1, BTreeSet::from([s2]), self.spp.sp.one, None)]);
+        while let Some((s1, set, sp, parent)) = todo.pop_front() {
+            // Drop the packets that are covered by a node with fewer states of `s2`
+            let nodes = antichain.entry(s1).or_default();
+            let mut sp = sp;
+            for (visited_set, visited_sp) in nodes.iter() {
+                if visited_set.is_subset(&set) {
+                    sp = self.spp.sp.difference(sp, *visited_sp);
+                }
+            }
+            if sp == self.spp.sp.zero {
+                continue;
+            }
+            match nodes.iter_mut().find(|(visited_set, _)| *visited_set == set) {
+                Some((_, visited_sp)) => *visited_sp = self.spp.sp.union(*visited_sp, sp),
+                None => nodes.push((set.clone(), sp)),
+            }
+            visits.push((sp, parent));
+            let visit = visits.len() - 1;
+
+            // Fail if `s1` accepts a packet pair that none of the states in the set accept
+            let mut accepted = self.spp.zero;
+            for &s2 in &set {
+                let epsilon_spp = self.epsilon(s2);
+                accepted = self.spp.union(accepted, epsilon_spp);
+            }
+            let epsilon_spp = self.epsilon(s1);
+            let rejected = self.spp.difference(epsilon_spp, accepted);
+            let reachable = self.spp.ibwd(sp);
+            let rejected = self.spp.intersect(rejected, reachable);
+            if rejected != self.spp.zero {
+                return Some(self.reconstruct_trace(&visits, visit, rejected));
+            }
+
+            // Partition the packet pairs by the set of states of `s2` they lead to
+            let mut parts = vec![(self.spp.top, BTreeSet::new())];
+            for &s2 in &set {
+                for (t2, spp2) in self.delta(s2).transitions {
+                    let mut refined = vec![];
+                    for (part, targets) in parts {
+                        let inside = self.spp.intersect(part, spp2);
+                        if inside != self.spp.zero {
+                            let mut targets = targets.clone();
+                            targets.insert(t2);
+                            refined.push((inside, targets));
+                        }
+                        let outside = self.spp.difference(part, spp2);
+                        if outside != self.spp.zero {
+                            refined.push((outside, targets));
+                        }
+                    }
+                    parts = refined;
+                }
+            }
+            for (t1, spp1) in self.delta(s1).transitions {
+                for (part, targets) in &parts {
+                    let spp = self.spp.intersect(spp1, *part);
+                    let next_sp = self.spp.push(sp, spp);
+                    if next_sp != self.spp.sp.zero {
+                        todo.push_back((t1, targets.clone(), next_sp, Some((visit, spp))));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Checks whether the given state accepts the concrete trace
+    pub fn accepts(&mut self, state: State, trace: &Trace) -> bool {
+        let n = trace.packets.len();
+        let mut state = state;
+        for i in 0..n - 2 {
+            let (input, output) = (&trace.packets[i], &trace.packets[i + 1]);
+            let next = self
+                .delta(state)
+                .transitions
+                .into_iter()
+                .find(|&(_, spp)| self.spp.contains(spp, input, output));
+            match next {
+                Some((next, _)) => state = next,
+                None => return false,
+            }
+        }
+        let epsilon_spp = self.epsilon(state);
+        self.spp
+            .contains(epsilon_spp, &trace.packets[n - 2], &trace.packets[n - 1])
     }
 
     /// Returns a string representation of the AExpr for the given state
@@ -888,7 +2139,46 @@ impl Aut {
                 self.state_to_string(*e1),
                 self.state_to_string(*e2)
             ),
+            AExpr::LtlRelease(e1, e2) => format!(
+                "({} R {})",
+                self.state_to_string(*e1),
+                self.state_to_string(*e2)
+            ),
+            AExpr::LtlYesterday(e) => format!("Y({})", self.state_to_string(*e)),
+            AExpr::LtlSince(e1, e2) => format!(
+                "({} S {})",
+                self.state_to_string(*e1),
+                self.state_to_string(*e2)
+            ),
+            AExpr::History(e, memory) => {
+                let memory: Vec<String> = memory
+                    .iter()
+                    .map(|&(op, remembered)| {
+                        format!("{} ↦ {}", self.state_to_string(op), self.state_to_string(remembered))
+                    })
+                    .collect();
+                format!("{} [{}]", self.state_to_string(*e), memory.join(", "))
+            }
+            AExpr::Rev(e) => format!("rev ({})", self.state_to_string(*e)),
+            AExpr::RevStates(e, states) => {
+                let states: Vec<String> = states.iter().map(|&s| self.state_to_string(s)).collect();
+                format!("rev {{{}}} ({})", states.join(", "), self.state_to_string(*e))
+            }
             AExpr::Top => "⊤".to_string(),
+            AExpr::Proj(fields, e, hidden_sp) => {
+                let fields: Vec<String> = fields.iter().map(|field| format!("x{}", field)).collect();
+                let hidden = if *hidden_sp == self.spp.sp.one {
+                    String::new()
+                } else {
+                    format!(" [hidden: {:?}]", hidden_sp)
+                };
+                format!(
+                    "proj {{{}}}{} ({})",
+                    fields.join(", "),
+                    hidden,
+                    self.state_to_string(*e)
+                )
+            }
         }
     }
 
@@ -911,14 +2201,392 @@ impl Aut {
             AExpr::Xor(e1, e2)
             | AExpr::Difference(e1, e2)
             | AExpr::Sequence(e1, e2)
-            | AExpr::LtlUntil(e1, e2) => {
+            | AExpr::LtlUntil(e1, e2)
+            | AExpr::LtlRelease(e1, e2)
+            | AExpr::LtlSince(e1, e2) => {
                 self.collect_spps(*e1, spps);
                 self.collect_spps(*e2, spps);
             }
-            AExpr::Complement(e) | AExpr::Star(e) | AExpr::LtlNext(e) => {
+            AExpr::Complement(e)
+            | AExpr::Star(e)
+            | AExpr::LtlNext(e)
+            | AExpr::LtlYesterday(e)
+            | AExpr::Rev(e)
+            | AExpr::Proj(_, e, _) => {
+                self.collect_spps(*e, spps);
+            }
+            AExpr::RevStates(e, states) => {
+                self.collect_spps(*e, spps);
+                for &s in states {
+                    self.collect_spps(s, spps);
+                }
+            }
+            AExpr::History(e, memory) => {
                 self.collect_spps(*e, spps);
+                for &(op, remembered) in memory {
+                    self.collect_spps(op, spps);
+                    self.collect_spps(remembered, spps);
+                }
             }
             AExpr::Dup | AExpr::Top => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_witness_empty() {
+        let mut aut = Aut::new(2);
+        for input in ["0", "x0==1 & x0==0", "dup - dup", "(x0:=1; dup)* ^ (x0:=1; dup)*"] {
+            let state = aut.parse_state(input);
+            assert_eq!(aut.witness(state), None, "{} should be empty", input);
+            assert!(aut.is_empty(state));
+        }
+    }
+
+    #[test]
+    fn test_witness_is_accepted() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "1",
+            "x0==1; x1:=1",
+            "x0:=1; dup; x1==1",
+            "x0==1; dup; x0:=0; dup; x1:=1",
+            "(x0:=1; dup)* - 1",
+            "!(dup; dup)",
+            "X X (x1==1)",
+            "(x0==0; T) U end",
+        ] {
+            let state = aut.parse_state(input);
+            let trace = aut.witness(state).expect(input);
+            assert!(aut.accepts(state, &trace), "{} does not accept {}", input, trace);
+        }
+    }
+
+    #[test]
+    fn test_witness_format() {
+        let mut aut = Aut::new(2);
+        let state = aut.parse_state("x0==1; x1:=1; dup; x0:=0");
+        let trace = aut.witness(state).unwrap();
+        assert_eq!(trace.to_string(), "x0=1,x1=0 -> x0=1,x1=1 -> x0=0,x1=1");
+        assert_eq!(trace.num_steps(), 1);
+    }
+
+    #[test]
+    fn test_shortest_witness() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "0",
+            "1",
+            "x0==1; x1:=1",
+            "(x0:=1; dup)* - 1",
+            "(x0:=1; dup; dup; dup) + (x1==1; dup; x0:=1; dup)",
+            "!(dup; dup)",
+            "X X (x1==1)",
+            "(x0==0; T) U end",
+        ] {
+            let state = aut.parse_state(input);
+            let shortest = aut.shortest_witness(state);
+            let witness = aut.witness(state);
+            assert_eq!(shortest.is_some(), witness.is_some(), "{}", input);
+            if let (Some(shortest), Some(witness)) = (shortest, witness) {
+                assert!(aut.accepts(state, &shortest), "{} does not accept {}", input, shortest);
+                assert!(shortest.num_steps() <= witness.num_steps(), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shortest_witness_is_canonical() {
+        let mut aut = Aut::new(2);
+        let state = aut.parse_state("(x0:=1; dup; dup; dup) + (x1==1; dup; x0:=1; dup)");
+        let trace = aut.shortest_witness(state).unwrap();
+        assert_eq!(
+            trace.to_string(),
+            "x0=0,x1=1 -> x0=0,x1=1 -> x0=1,x1=1 -> x0=1,x1=1"
+        );
+        let state = aut.parse_state("!(dup; dup)");
+        let trace = aut.shortest_witness(state).unwrap();
+        assert_eq!(trace.to_string(), "x0=0,x1=0 -> x0=0,x1=0");
+    }
+
+    #[test]
+    fn test_equiv() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            ("x0:=1; dup; x0==1", "x0:=1; dup"),
+            ("x0:=0; x0:=1", "x0:=1"),
+            ("x0==1 + x0==0", "1"),
+            ("(x0:=1; dup)*", "1 + (x0:=1; dup; (x0:=1; dup)*)"),
+            ("(dup + x1:=1)*", "dup*; (x1:=1; dup*)*"),
+            ("!(dup; dup)", "!(dup; dup) & T"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert!(aut.equiv(s1, s2), "{} should be equivalent to {}", e1, e2);
+            assert!(aut.equiv(s2, s1), "{} should be equivalent to {}", e2, e1);
+        }
+    }
+
+    #[test]
+    fn test_past() {
+        let mut aut = Aut::new(2);
+        let (p, q) = ("(x0==1; T)", "(x1==1; T)");
+        for (e1, e2) in [
+            // There is nothing before the first position
+            (format!("Y {p}"), "0".to_string()),
+            (format!("Z {p}"), "T".to_string()),
+            (format!("O {p}"), p.to_string()),
+            (format!("H {p}"), p.to_string()),
+            (format!("X Y {p}"), format!("{p} & X T")),
+            (format!("X Z {p}"), format!("{p} & X T")),
+            (format!("X X Y Y {p}"), format!("{p} & X X T")),
+            (format!("G H {p}"), format!("G {p}")),
+            (format!("F ({q} & Y {p})"), format!("F ({p} & X {q})")),
+            (format!("F ({q} & O {p})"), format!("F ({p} & F {q})")),
+            (
+                format!("X X ({q} S {p})"),
+                format!("X X {p} + X ({p} & X {q}) + ({p} & X ({q} & X {q}))"),
+            ),
+            // If you are at q, you must have passed p
+            (format!("G (!{q} + O {p})"), format!("(!{q} U {p}) + G !{q}")),
+        ] {
+            let (s1, s2) = (aut.parse_state(&e1), aut.parse_state(&e2));
+            assert_eq!(aut.equiv_counterexample(s1, s2), None, "{} vs {}", e1, e2);
+        }
+        for (e1, e2) in [
+            (format!("X Y {p}"), "X T".to_string()),
+            (format!("F ({q} & Y {p})"), format!("F ({q} & {p})")),
+            (format!("X X ({q} S {p})"), format!("X X ({p} + {q})")),
+        ] {
+            let (s1, s2) = (aut.parse_state(&e1), aut.parse_state(&e2));
+            let trace = aut.equiv_counterexample(s1, s2).expect(&e1);
+            assert_ne!(aut.accepts(s1, &trace), aut.accepts(s2, &trace), "{}", trace);
+        }
+        let state = aut.parse_state(&format!("F ({q} & Y {p})"));
+        let trace = aut.witness(state).unwrap();
+        assert!(aut.accepts(state, &trace));
+        assert_eq!(trace.num_steps(), 1);
+    }
+
+    #[test]
+    fn test_ldl() {
+        let mut aut = Aut::new(2);
+        let p = "(x1==1; T)";
+        for (e1, e2) in [
+            (format!("<X 1> {p}"), format!("X {p}")),
+            (format!("<(X 1)*> {p}"), format!("F {p}")),
+            (format!("[(X 1)*] {p}"), format!("G {p}")),
+            (format!("[X 1] {p}"), format!("X {p} + end")),
+            (format!("<x0==1; dup> {p}"), format!("x0==1; dup; {p}")),
+            (format!("[x0==1] {p}"), format!("!(x0==1; T) + {p}")),
+            // dup does not change the packet
+            (format!("[dup] {p}"), format!("!(dup; T) + X {p}")),
+        ] {
+            let (s1, s2) = (aut.parse_state(&e1), aut.parse_state(&e2));
+            assert_eq!(aut.equiv_counterexample(s1, s2), None, "{} vs {}", e1, e2);
+        }
+        // After any path through the core switch x0==1, x1 is set
+        let (s1, s2) = (
+            aut.parse_state(&format!("[(x0==1; dup)*] {p}")),
+            aut.parse_state(&format!("G {p}")),
+        );
+        assert!(aut.includes(s2, s1));
+        assert!(!aut.includes(s1, s2));
+    }
+
+    #[test]
+    fn test_rev() {
+        let mut aut = Aut::new(2);
+        let havoc0 = "(x0:=0 + x0:=1)";
+        let havoc1 = "(x1:=0 + x1:=1)";
+        for (e1, e2) in [
+            ("rev dup".to_string(), "dup".to_string()),
+            ("rev (x0:=1)".to_string(), format!("x0==1; {havoc0}")),
+            (
+                "rev (x0:=1; dup; x1:=1)".to_string(),
+                format!("x1==1; {havoc1}; dup; x0==1; {havoc0}"),
+            ),
+            (
+                "rev (x0:=1; dup)*".to_string(),
+                format!("(dup; x0==1; {havoc0})*"),
+            ),
+            (
+                "rev !(x0:=1; dup)".to_string(),
+                format!("!(dup; x0==1; {havoc0})"),
+            ),
+            (
+                "rev ((x0==1; dup) + (x1==0; dup; dup))".to_string(),
+                "(dup; x0==1) + (dup; dup; x1==0)".to_string(),
+            ),
+            // The reversed string tests every packet but the first one
+            ("rev F (x0==1; T)".to_string(), "X F (x0==1; T) + (T; x0==1)".to_string()),
+            ("rev X X T".to_string(), "X X T".to_string()),
+            ("rev (x0:=1; dup)* ; rev rev (x1:=1; dup)".to_string(), format!("(dup; x0==1; {havoc0})*; x1:=1; dup")),
+        ] {
+            let (s1, s2) = (aut.parse_state(&e1), aut.parse_state(&e2));
+            assert_eq!(aut.equiv_counterexample(s1, s2), None, "{} vs {}", e1, e2);
+        }
+        // Reversing a witness of e gives a witness of rev e
+        for input in ["x0:=1; dup; x1==1; dup; x0:=0", "(x0==1; dup)* ; F (x1==1; T)"] {
+            let state = aut.parse_state(input);
+            let reversed = aut.parse_state(&format!("rev ({})", input));
+            let mut trace = aut.witness(state).unwrap();
+            trace.packets.reverse();
+            assert!(aut.accepts(reversed, &trace), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_equiv_counterexample() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            ("x0:=1; dup; x0==0", "x0:=1; dup"),
+            ("x0:=1", "x1:=1"),
+            ("(x0:=1; dup)*", "(x0:=1; dup; dup)*"),
+            ("dup; dup", "dup"),
+            ("X X (x1==1)", "X (x1==1)"),
+            ("0", "(x0==0; T) U end"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            let trace = aut.equiv_counterexample(s1, s2).expect(e1);
+            assert_ne!(
+                aut.accepts(s1, &trace),
+                aut.accepts(s2, &trace),
+                "{} does not distinguish {} and {}",
+                trace,
+                e1,
+                e2
+            );
+        }
+    }
+
+    #[test]
+    fn test_includes() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            ("0", "x0:=1; dup"),
+            ("x0:=1; dup; x0==1", "x0:=1; dup"),
+            ("x0==1; x1:=1", "x1:=1"),
+            ("(x0:=1; dup; dup)*", "(x0:=1; dup)*"),
+            ("X X (x1==1)", "!(dup; dup) + X X T"),
+            ("x0:=1 & x1:=1", "x0:=1 + x1:=1"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert!(aut.includes(s1, s2), "{} should be included in {}", e1, e2);
+        }
+    }
+
+    #[test]
+    fn test_includes_counterexample() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            ("x0:=1; dup", "0"),
+            ("x1:=1", "x0==1; x1:=1"),
+            ("(x0:=1; dup)*", "(x0:=1; dup; dup)*"),
+            ("!(dup; dup)", "X X T"),
+            ("x0:=1 + x1:=1", "x0:=1 & x1:=1"),
+            ("(dup + x1:=1)*", "dup*"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            let trace = aut.includes_counterexample(s1, s2).expect(e1);
+            assert!(aut.accepts(s1, &trace), "{} does not accept {}", e1, trace);
+            assert!(!aut.accepts(s2, &trace), "{} accepts {}", e2, trace);
+        }
+    }
+
+    #[test]
+    fn test_explore() {
+        let mut aut = Aut::new(2);
+        let root = aut.parse_state("(x0:=1; dup; dup)* + x1==1; dup");
+        let explicit = aut.explore(root);
+        assert!(explicit.complete);
+        assert_eq!(explicit.states[0], root);
+        assert_eq!(explicit.index_of(root), Some(0));
+        assert_eq!(explicit.labels.len(), explicit.num_states());
+        assert_eq!(explicit.epsilons.len(), explicit.num_states());
+        for (i, &state) in explicit.states.iter().enumerate() {
+            assert_eq!(explicit.epsilons[i], aut.epsilon(state));
+            let delta = aut.delta(state);
+            assert_eq!(explicit.transitions[i].transitions.len(), delta.transitions.len());
+            for (&target, &spp) in &explicit.transitions[i].transitions {
+                assert_eq!(delta.transitions[&explicit.states[target]], spp);
+            }
+        }
+
+        let limited = aut.explore_with_limit(root, 2);
+        assert!(!limited.complete);
+        assert_eq!(limited.num_states(), 2);
+        assert!(limited.num_transitions() < explicit.num_transitions());
+        let exact = aut.explore_with_limit(root, explicit.num_states());
+        assert!(exact.complete);
+        assert_eq!(exact.num_transitions(), explicit.num_transitions());
+    }
+
+    #[test]
+    fn test_projection() {
+        let mut aut = Aut::new(3);
+        // The hidden bits must be consistent between consecutive steps
+        let empty = aut.parse_state("proj {x0} (x1:=1; dup; x1==0)");
+        assert!(aut.is_empty(empty));
+        for (e1, e2) in [
+            ("proj {x0} (x1:=1; dup; x1==1)", "proj {x0} dup"),
+            ("proj {x0, x1, x2} (x1:=1; dup)", "x1:=1; dup"),
+            ("proj {x0} (x1==0; x0:=1; dup; x1==0)", "proj {x0} (x0:=1; dup)"),
+            ("proj {x0} (x1:=1; dup; x1==1; x0:=1)", "proj {x0} ((x1==0; dup; x0:=1) + (x2==1; dup; x0:=1))"),
+            ("proj {x0} !(x1==1)", "proj {x0} T"),
+            ("!proj {x0} (x1==1; dup)", "!proj {x0} dup"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert!(aut.equiv(s1, s2), "{} should be equivalent to {}", e1, e2);
+        }
+        for (e1, e2) in [
+            ("proj {x0} (x1:=1; dup)", "x1:=1; dup"),
+            ("proj {x0} (x0:=1; dup)", "proj {x1} (x0:=1; dup)"),
+            ("proj {x0} (x1:=1; dup; x1==1; dup)", "proj {x0} (x1:=1; dup; x1==0; dup)"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert!(!aut.equiv(s1, s2), "{} should not be equivalent to {}", e1, e2);
+        }
+        // The projection hides exactly the other bits: changing them in a trace
+        // does not change whether it is accepted
+        let state = aut.parse_state("proj {x0} ((x1==1; x0:=1; dup)*; x2==0)");
+        let trace = aut.witness(state).unwrap();
+        let mut changed = trace.clone();
+        for packet in &mut changed.packets {
+            packet[1] = !packet[1];
+            packet[2] = !packet[2];
+        }
+        assert!(aut.accepts(state, &trace));
+        assert!(aut.accepts(state, &changed));
+    }
+
+    #[test]
+    fn test_gc() {
+        let mut aut = Aut::new(2);
+        let inputs = [
+            "(x0:=1; dup; dup; dup) + (x1==1; dup; x0:=1; dup)",
+            "(x0:=1; dup)* ^ (x0:=1; dup)*",
+            "!(dup; dup) & X X (x1==1)",
+        ];
+        let mut states = vec![];
+        let mut traces = vec![];
+        for input in inputs {
+            let state = aut.parse_state(input);
+            states.push(state);
+            traces.push(aut.shortest_witness(state).map(|t| t.to_string()));
+        }
+        let num_nodes = aut.spp_store().num_nodes() + aut.spp_store().sp.num_nodes();
+        aut.gc();
+        assert!(aut.spp_store().num_nodes() + aut.spp_store().sp.num_nodes() < num_nodes);
+        for (&state, trace) in states.iter().zip(&traces) {
+            assert_eq!(&aut.shortest_witness(state).map(|t| t.to_string()), trace);
+        }
+        // Hash consing of states still works after the collection
+        for (input, &state) in inputs.iter().zip(&states) {
+            assert_eq!(aut.parse_state(input), state);
+        }
+    }
+}
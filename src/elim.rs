@@ -0,0 +1,122 @@
+// Dup elimination (Phi): collapses an automaton into the SPP that relates the first
+// packet of each accepted string to its output packet, forgetting the trace in between.
+//
+// Each state s denotes the relation X_s = E_s + Σ_t A_st ; X_t, where E_s is the epsilon
+// of s and A_st the SPP of its transition to t. We solve these equations by state
+// elimination (Kleene's algorithm): Arden's rule turns a self-loop into X_s = A_ss* ; (E_s +
+// Σ_{t≠s} A_st ; X_t), after which X_s is substituted into the equations of the other states.
+
+use crate::aut::ExplicitAut;
+use crate::spp::{SPP, SPPstore};
+use std::collections::HashMap;
+
+/// The equation of one state: its epsilon and its transitions to the states not yet eliminated
+struct Equation {
+    epsilon: SPP,
+    transitions: HashMap<usize, SPP>,
+}
+
+/// Returns the input/output relation of the root (state 0) of an explicit automaton
+pub fn eliminate(explicit: &ExplicitAut, store: &mut SPPstore) -> SPP {
+    if explicit.num_states() == 0 {
+        return store.zero;
+    }
+    let mut equations: Vec<Equation> = (0..explicit.num_states())
+        .map(|state| Equation {
+            epsilon: explicit.epsilons[state],
+            transitions: explicit.transitions[state].get_transitions().clone(),
+        })
+        .collect();
+
+    // Eliminate the states from the last one discovered back to the root
+    for k in (0..explicit.num_states()).rev() {
+        // Arden's rule for the self-loop of k
+        let loop_spp = equations[k].transitions.remove(&k).unwrap_or(store.zero);
+        let loop_star = store.star(loop_spp);
+        let equation = &mut equations[k];
+        equation.epsilon = store.sequence(loop_star, equation.epsilon);
+        for spp in equation.transitions.values_mut() {
+            *spp = store.sequence(loop_star, *spp);
+        }
+        if k == 0 {
+            break;
+        }
+
+        // Substitute X_k into the states that still refer to it
+        let eliminated = std::mem::replace(
+            &mut equations[k],
+            Equation {
+                epsilon: store.zero,
+                transitions: HashMap::new(),
+            },
+        );
+        for equation in equations.iter_mut().take(k) {
+            let Some(to_k) = equation.transitions.remove(&k) else {
+                continue;
+            };
+            let epsilon = store.sequence(to_k, eliminated.epsilon);
+            equation.epsilon = store.union(equation.epsilon, epsilon);
+            for (&target, &spp) in &eliminated.transitions {
+                let spp = store.sequence(to_k, spp);
+                let entry = equation.transitions.entry(target).or_insert(store.zero);
+                *entry = store.union(*entry, spp);
+            }
+        }
+    }
+    equations[0].epsilon
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aut::Aut;
+
+    #[test]
+    fn test_eliminate_dups() {
+        let mut aut = Aut::new(2);
+        for (with_dups, without_dups) in [
+            ("dup", "1"),
+            ("0", "0"),
+            ("x0==1; dup; x1:=1", "x0==1; x1:=1"),
+            ("(x0:=1; dup; x1:=1)*", "(x0:=1; x1:=1)*"),
+            (
+                "((x0==0; x0:=1; dup) + (x0==1; x0:=0; dup))*; x1:=1",
+                "((x0==0; x0:=1) + (x0==1; x0:=0))*; x1:=1",
+            ),
+            ("(dup; x0==1; dup)*", "(x0==1)*"),
+            (
+                "(x0:=1; dup)* ; (x1==0; dup; x1:=1)*",
+                "(x0:=1)* ; (x1==0; x1:=1)*",
+            ),
+        ] {
+            let s1 = aut.parse_state(with_dups);
+            let s2 = aut.parse_state(without_dups);
+            assert_eq!(aut.eliminate(s1), aut.eliminate(s2), "{}", with_dups);
+        }
+    }
+
+    #[test]
+    fn test_eliminate_matches_witness() {
+        // The relation is empty iff the automaton is
+        let mut aut = Aut::new(2);
+        for input in [
+            "x0:=1; dup; x0==0",
+            "!(dup; dup) & dup",
+            "X X (x1==1)",
+            "(x0==0; T) U end",
+        ] {
+            let state = aut.parse_state(input);
+            let relation = aut.eliminate(state);
+            assert_eq!(
+                relation == aut.spp_store().zero,
+                aut.is_empty(state),
+                "{}",
+                input
+            );
+            // The first and last packets of a witness are related
+            if let Some(trace) = aut.witness(state) {
+                let (first, last) = (&trace.packets[0], trace.packets.last().unwrap());
+                assert!(aut.spp_store().contains(relation, first, last), "{}", input);
+            }
+        }
+    }
+}
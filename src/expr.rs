@@ -1,4 +1,5 @@
-use crate::pre::{Field, Value};
+use crate::fields::Fields;
+use crate::pre::{Field, IntValue, Value};
 
 /// Represents NetKAT expressions with LTL extensions
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -8,6 +9,10 @@ pub enum Expr {
     Top,                  // T
     Assign(Field, Value), // field := value
     Test(Field, Value),   // field == value
+    // Multi-bit fields occupy `width` consecutive bits starting at the given field,
+    // with the most significant bit first
+    AssignInt(Field, u32, IntValue), // x[start..start+width] := value
+    TestInt(Field, u32, IntValue),   // x[start..start+width] == value
     Union(Exp, Exp),      // e1 + e2
     Intersect(Exp, Exp),  // e1 & e2
     Xor(Exp, Exp),        // e1 ^ e2
@@ -18,7 +23,14 @@ pub enum Expr {
     Dup,                  // dup
     LtlNext(Exp),         // X e
     LtlUntil(Exp, Exp),   // e1 U e2
+    LtlYesterday(Exp),    // Y e
+    LtlSince(Exp, Exp),   // e1 S e2
+    LdlDiamond(Exp, Exp), // <e1> e2
+    LdlBox(Exp, Exp),     // [e1] e2
     End,                  // end
+    Rev(Exp),             // rev e: the converse, with every string reversed
+    // Projection: keeps the given bits (sorted) and hides the other ones at every trace position
+    Proj(Vec<Field>, Exp), // proj {x0, x1} e
 }
 
 /// Represents a boxed expression
@@ -40,6 +52,12 @@ impl Expr {
     pub fn test(field: Field, value: Value) -> Exp {
         Box::new(Expr::Test(field, value))
     }
+    pub fn assign_int(start: Field, width: u32, value: IntValue) -> Exp {
+        Box::new(Expr::AssignInt(start, width, value))
+    }
+    pub fn test_int(start: Field, width: u32, value: IntValue) -> Exp {
+        Box::new(Expr::TestInt(start, width, value))
+    }
     pub fn union(e1: Exp, e2: Exp) -> Exp {
         Box::new(Expr::Union(e1, e2))
     }
@@ -81,21 +99,84 @@ impl Expr {
             Expr::ltl_globally(e1),
         ))
     }
+    pub fn ltl_yesterday(e: Exp) -> Exp {
+        Box::new(Expr::LtlYesterday(e))
+    }
+    pub fn ltl_since(e1: Exp, e2: Exp) -> Exp {
+        Box::new(Expr::LtlSince(e1, e2))
+    }
+    /// LDL diamond: some prefix of the string matches the path `e1`, and the rest matches `e2`
+    pub fn ldl_diamond(e1: Exp, e2: Exp) -> Exp {
+        Box::new(Expr::LdlDiamond(e1, e2))
+    }
+    /// LDL box: whenever a prefix of the string matches the path `e1`, the rest matches `e2`
+    pub fn ldl_box(e1: Exp, e2: Exp) -> Exp {
+        Box::new(Expr::LdlBox(e1, e2))
+    }
     pub fn end() -> Exp {
         Box::new(Expr::End)
     }
+    pub fn rev(e: Exp) -> Exp {
+        Box::new(Expr::Rev(e))
+    }
+    pub fn proj(mut fields: Vec<Field>, e: Exp) -> Exp {
+        fields.sort();
+        fields.dedup();
+        Box::new(Expr::Proj(fields, e))
+    }
 
     pub fn num_fields(&self) -> u32 {
         match self {
             Expr::Zero | Expr::One | Expr::Top | Expr::Dup | Expr::End => 0,
             Expr::Assign(field, _) | Expr::Test(field, _) => field + 1,
+            Expr::AssignInt(start, width, _) | Expr::TestInt(start, width, _) => start + width,
+            Expr::Union(e1, e2)
+            | Expr::Intersect(e1, e2)
+            | Expr::Xor(e1, e2)
+            | Expr::Difference(e1, e2)
+            | Expr::Sequence(e1, e2)
+            | Expr::LtlUntil(e1, e2)
+            | Expr::LtlSince(e1, e2)
+            | Expr::LdlDiamond(e1, e2)
+            | Expr::LdlBox(e1, e2) => e1.num_fields().max(e2.num_fields()),
+            Expr::Complement(e)
+            | Expr::Star(e)
+            | Expr::LtlNext(e)
+            | Expr::LtlYesterday(e)
+            | Expr::Rev(e) => e.num_fields(),
+            Expr::Proj(fields, e) => {
+                let max = fields.last().map_or(0, |field| field + 1);
+                e.num_fields().max(max)
+            }
+        }
+    }
+
+    /// Whether the expression contains a past operator (`Y` or `S`)
+    pub fn has_past(&self) -> bool {
+        match self {
+            Expr::Zero
+            | Expr::One
+            | Expr::Top
+            | Expr::Dup
+            | Expr::End
+            | Expr::Assign(..)
+            | Expr::Test(..)
+            | Expr::AssignInt(..)
+            | Expr::TestInt(..) => false,
+            Expr::LtlYesterday(_) | Expr::LtlSince(..) => true,
             Expr::Union(e1, e2)
             | Expr::Intersect(e1, e2)
             | Expr::Xor(e1, e2)
             | Expr::Difference(e1, e2)
             | Expr::Sequence(e1, e2)
-            | Expr::LtlUntil(e1, e2) => e1.num_fields().max(e2.num_fields()),
-            Expr::Complement(e) | Expr::Star(e) | Expr::LtlNext(e) => e.num_fields(),
+            | Expr::LtlUntil(e1, e2)
+            | Expr::LdlDiamond(e1, e2)
+            | Expr::LdlBox(e1, e2) => e1.has_past() || e2.has_past(),
+            Expr::Complement(e)
+            | Expr::Star(e)
+            | Expr::LtlNext(e)
+            | Expr::Rev(e)
+            | Expr::Proj(_, e) => e.has_past(),
         }
     }
 
@@ -118,6 +199,21 @@ impl Expr {
         Box::new(Expr::Complement(until_expr))
     }
 
+    /// Weak yesterday operator `(Z e ≡ ¬Y ¬e)`, which holds at the first position
+    pub fn ltl_weak_yesterday(e: Exp) -> Exp {
+        Box::new(Expr::Complement(Expr::ltl_yesterday(Expr::complement(e))))
+    }
+
+    /// Once operator `(O e ≡ true S e)`
+    pub fn ltl_once(e: Exp) -> Exp {
+        Box::new(Expr::LtlSince(Expr::top(), e))
+    }
+
+    /// Historically operator `(H e ≡ ¬O ¬e)`
+    pub fn ltl_historically(e: Exp) -> Exp {
+        Box::new(Expr::Complement(Expr::ltl_once(Expr::complement(e))))
+    }
+
     /// Strong release operator `(e1 M e2 = e1 R e2 /\ F e1)`
     pub fn ltl_strong_release(e1: Exp, e2: Exp) -> Exp {
         Box::new(Expr::Intersect(
@@ -127,29 +223,232 @@ impl Expr {
     }
 }
 
-impl std::fmt::Display for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// Helper for displaying an expression with named fields, see `Expr::display`
+pub struct ExprDisplay<'a> {
+    expr: &'a Expr,
+    fields: &'a Fields,
+}
+
+impl Expr {
+    /// Displays the expression using the names of the declared fields where possible.
+    /// The output is in the syntax of the parser, with as few parentheses as its
+    /// precedence levels allow, so that parsing it gives back the same expression.
+    pub fn display<'a>(&'a self, fields: &'a Fields) -> ExprDisplay<'a> {
+        ExprDisplay { expr: self, fields }
+    }
+}
+
+// The precedence levels of the parser, from the loosest to the tightest binding
+const UNTIL: u8 = 0; // U, R, S (right associative)
+const SEQUENCE: u8 = 1; // ; (left associative)
+const ADDITIVE: u8 = 2; // +, ^, - (left associative)
+const INTERSECT: u8 = 3; // & (left associative)
+const PREFIX: u8 = 4; // !, X, F, G, Y, Z, O, H, <e>, [e], proj, rev
+const STAR: u8 = 5; // e*
+const PRIMARY: u8 = 6; // Constants, field operations and parentheses
+
+/// A derived operator that the parser desugars, recognized again for printing
+enum Sugar<'a> {
+    Prefix(&'static str, &'a Expr), // F e, G e, Z e, O e, H e
+    Release(&'a Expr, &'a Expr),    // e1 R e2
+}
+
+impl Expr {
+    // Recognizes the desugared forms of `F`, `G`, `R`, `Z`, `O` and `H` (see `Parser::parse_unary`)
+    fn sugar(&self) -> Option<Sugar<'_>> {
         match self {
+            Expr::LtlUntil(e1, e2) if **e1 == Expr::Top => Some(Sugar::Prefix("F", e2)),
+            Expr::LtlSince(e1, e2) if **e1 == Expr::Top => Some(Sugar::Prefix("O", e2)),
+            Expr::Complement(e) => match &**e {
+                Expr::LtlUntil(e1, e2) => match (&**e1, &**e2) {
+                    (Expr::Top, Expr::Complement(e2)) => Some(Sugar::Prefix("G", e2)),
+                    (Expr::Complement(e1), Expr::Complement(e2)) => Some(Sugar::Release(e1, e2)),
+                    _ => None,
+                },
+                Expr::LtlSince(e1, e2) => match (&**e1, &**e2) {
+                    (Expr::Top, Expr::Complement(e2)) => Some(Sugar::Prefix("H", e2)),
+                    _ => None,
+                },
+                Expr::LtlYesterday(e) => match &**e {
+                    Expr::Complement(e) => Some(Sugar::Prefix("Z", e)),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The precedence level of the operator at the root of the printed expression
+    fn precedence(&self) -> u8 {
+        match self.sugar() {
+            Some(Sugar::Prefix(..)) => return PREFIX,
+            Some(Sugar::Release(..)) => return UNTIL,
+            None => {}
+        }
+        match self {
+            Expr::LtlUntil(..) | Expr::LtlSince(..) => UNTIL,
+            Expr::Sequence(..) => SEQUENCE,
+            Expr::Union(..) | Expr::Xor(..) | Expr::Difference(..) => ADDITIVE,
+            Expr::Intersect(..) => INTERSECT,
+            Expr::Complement(_)
+            | Expr::LtlNext(_)
+            | Expr::LtlYesterday(_)
+            | Expr::LdlDiamond(..)
+            | Expr::LdlBox(..)
+            | Expr::Rev(_)
+            | Expr::Proj(..) => PREFIX,
+            Expr::Star(_) => STAR,
+            Expr::Zero
+            | Expr::One
+            | Expr::Top
+            | Expr::Dup
+            | Expr::End
+            | Expr::Assign(..)
+            | Expr::Test(..)
+            | Expr::AssignInt(..)
+            | Expr::TestInt(..) => PRIMARY,
+        }
+    }
+}
+
+impl ExprDisplay<'_> {
+    // Writes `expr` in parentheses if it binds looser than `level`
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, expr: &Expr, level: u8) -> std::fmt::Result {
+        if expr.precedence() < level {
+            write!(f, "(")?;
+            self.write_bare(f, expr)?;
+            write!(f, ")")
+        } else {
+            self.write_bare(f, expr)
+        }
+    }
+
+    // Writes a binary operator whose operands bind at least as tight as `left` and `right`
+    fn write_infix(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        e1: &Expr,
+        op: &str,
+        e2: &Expr,
+        (left, right): (u8, u8),
+    ) -> std::fmt::Result {
+        self.write(f, e1, left)?;
+        write!(f, " {} ", op)?;
+        self.write(f, e2, right)
+    }
+
+    fn write_bare(&self, f: &mut std::fmt::Formatter<'_>, expr: &Expr) -> std::fmt::Result {
+        let fields = self.fields;
+        match expr.sugar() {
+            Some(Sugar::Prefix(op, e)) => {
+                write!(f, "{} ", op)?;
+                return self.write(f, e, PREFIX);
+            }
+            Some(Sugar::Release(e1, e2)) => {
+                return self.write_infix(f, e1, "R", e2, (SEQUENCE, UNTIL));
+            }
+            None => {}
+        }
+        match expr {
             Expr::Zero => write!(f, "0"),
             Expr::One => write!(f, "1"),
-            Expr::Top => write!(f, "⊤"),
-            Expr::Assign(field, value) => {
-                write!(f, "x{} := {}", field, if *value { "1" } else { "0" })
+            Expr::Top => write!(f, "T"),
+            Expr::Assign(field, value) => match fields.find(*field, 1) {
+                Some(decl) => write!(f, "{} := {}", decl.name, *value as u8),
+                None => write!(f, "x{} := {}", field, *value as u8),
+            },
+            Expr::Test(field, value) => match fields.find(*field, 1) {
+                Some(decl) => write!(f, "{} == {}", decl.name, *value as u8),
+                None => write!(f, "x{} == {}", field, *value as u8),
+            },
+            Expr::AssignInt(start, width, value) => match fields.find(*start, *width) {
+                Some(decl) => write!(f, "{} := {}", decl.name, value),
+                None => write!(f, "x[{}..{}] := {}", start, start + width, value),
+            },
+            Expr::TestInt(start, width, value) => match fields.find(*start, *width) {
+                Some(decl) => write!(f, "{} == {}", decl.name, value),
+                None => write!(f, "x[{}..{}] == {}", start, start + width, value),
+            },
+            Expr::Union(e1, e2) => self.write_infix(f, e1, "+", e2, (ADDITIVE, INTERSECT)),
+            Expr::Xor(e1, e2) => self.write_infix(f, e1, "^", e2, (ADDITIVE, INTERSECT)),
+            Expr::Difference(e1, e2) => self.write_infix(f, e1, "-", e2, (ADDITIVE, INTERSECT)),
+            Expr::Intersect(e1, e2) => self.write_infix(f, e1, "&", e2, (INTERSECT, PREFIX)),
+            Expr::Sequence(e1, e2) => self.write_infix(f, e1, ";", e2, (SEQUENCE, ADDITIVE)),
+            Expr::LtlUntil(e1, e2) => self.write_infix(f, e1, "U", e2, (SEQUENCE, UNTIL)),
+            Expr::LtlSince(e1, e2) => self.write_infix(f, e1, "S", e2, (SEQUENCE, UNTIL)),
+            Expr::Complement(e) => {
+                write!(f, "!")?;
+                self.write(f, e, PREFIX)
+            }
+            Expr::LtlNext(e) => {
+                write!(f, "X ")?;
+                self.write(f, e, PREFIX)
+            }
+            Expr::LtlYesterday(e) => {
+                write!(f, "Y ")?;
+                self.write(f, e, PREFIX)
+            }
+            Expr::Rev(e) => {
+                write!(f, "rev ")?;
+                self.write(f, e, PREFIX)
             }
-            Expr::Test(field, value) => {
-                write!(f, "x{} == {}", field, if *value { "1" } else { "0" })
+            // The path of a modality is parsed up to the closing '>' or ']'
+            Expr::LdlDiamond(e1, e2) => {
+                write!(f, "<")?;
+                self.write(f, e1, UNTIL)?;
+                write!(f, "> ")?;
+                self.write(f, e2, PREFIX)
+            }
+            Expr::LdlBox(e1, e2) => {
+                write!(f, "[")?;
+                self.write(f, e1, UNTIL)?;
+                write!(f, "] ")?;
+                self.write(f, e2, PREFIX)
+            }
+            Expr::Star(e) => {
+                // Field operations are parenthesized for readability, as in `(x0 == 1)*`
+                match **e {
+                    Expr::Assign(..) | Expr::Test(..) | Expr::AssignInt(..) | Expr::TestInt(..) => {
+                        write!(f, "(")?;
+                        self.write_bare(f, e)?;
+                        write!(f, ")")?;
+                    }
+                    _ => self.write(f, e, STAR)?,
+                }
+                write!(f, "*")
             }
-            Expr::Union(e1, e2) => write!(f, "({} + {})", e1, e2),
-            Expr::Intersect(e1, e2) => write!(f, "({} & {})", e1, e2),
-            Expr::Xor(e1, e2) => write!(f, "({} ^ {})", e1, e2),
-            Expr::Difference(e1, e2) => write!(f, "({} - {})", e1, e2),
-            Expr::Complement(e) => write!(f, "!{}", e),
-            Expr::Sequence(e1, e2) => write!(f, "({} ; {})", e1, e2),
-            Expr::Star(e) => write!(f, "({})*", e),
             Expr::Dup => write!(f, "dup"),
-            Expr::LtlNext(e) => write!(f, "X({})", e),
-            Expr::LtlUntil(e1, e2) => write!(f, "({} U {})", e1, e2),
             Expr::End => write!(f, "end"),
+            Expr::Proj(kept, e) => {
+                // Show a declared field by its name if all of its bits are kept
+                let mut names = vec![];
+                let mut named = vec![];
+                for decl in fields.decls() {
+                    let bits: Vec<Field> = (decl.start..decl.start + decl.width).collect();
+                    if bits.iter().all(|bit| kept.contains(bit)) {
+                        names.push(decl.name.clone());
+                        named.extend(bits);
+                    }
+                }
+                for field in kept.iter().filter(|field| !named.contains(field)) {
+                    names.push(format!("x{}", field));
+                }
+                write!(f, "proj {{{}}} ", names.join(", "))?;
+                self.write(f, e, PREFIX)
+            }
         }
     }
 }
+
+impl std::fmt::Display for ExprDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, self.expr, UNTIL)
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(&Fields::new()).fmt(f)
+    }
+}
@@ -0,0 +1,149 @@
+// Named fields: a layout that maps field names to ranges of packet bits.
+
+use crate::parser::{tokenize, Token};
+use crate::pre::{Field, IntValue, Value};
+use std::fmt;
+
+/// A declared field `name : width`, occupying the bits `start..start+width`
+/// with the most significant bit first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDecl {
+    pub name: String,
+    pub start: Field,
+    pub width: u32,
+}
+
+/// The field layout of a program. Fields are allocated consecutively from bit 0
+/// in declaration order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields {
+    decls: Vec<FieldDecl>,
+}
+
+impl Fields {
+    pub fn new() -> Self {
+        Fields { decls: vec![] }
+    }
+
+    /// Declares a new field of `width` bits after the previously declared ones
+    pub fn declare(&mut self, name: &str, width: u32) -> Result<&FieldDecl, String> {
+        // Names that do not lex as an identifier would print as something else, e.g. `x3` as bit 3
+        if !matches!(tokenize(name).as_deref(), Ok([Token::Ident(ident)]) if ident == name) {
+            return Err(format!(
+                "`{}` is not a valid field name: use a lowercase letter or `_` followed by letters, digits or `_`, \
+                 other than a keyword or a bit like `x3`",
+                name
+            ));
+        }
+        if self.get(name).is_some() {
+            return Err(format!("Field `{}` is already declared", name));
+        }
+        if width == 0 || width > IntValue::BITS {
+            return Err(format!(
+                "Field `{}` must be between 1 and {} bits wide, found {}",
+                name,
+                IntValue::BITS,
+                width
+            ));
+        }
+        let start = self.num_bits();
+        self.decls.push(FieldDecl {
+            name: name.to_string(),
+            start,
+            width,
+        });
+        Ok(self.decls.last().unwrap())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FieldDecl> {
+        self.decls.iter().find(|decl| decl.name == name)
+    }
+
+    /// Finds the field that occupies exactly the bits `start..start+width`
+    pub fn find(&self, start: Field, width: u32) -> Option<&FieldDecl> {
+        self.decls
+            .iter()
+            .find(|decl| decl.start == start && decl.width == width)
+    }
+
+    pub fn decls(&self) -> &[FieldDecl] {
+        &self.decls
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decls.is_empty()
+    }
+
+    /// The number of bits taken up by the declared fields
+    pub fn num_bits(&self) -> u32 {
+        self.decls.last().map_or(0, |decl| decl.start + decl.width)
+    }
+
+    /// Formats a packet as `sw=5,dst=1`, showing bits outside the declared fields as `x7=1`
+    pub fn format_packet(&self, packet: &[Value]) -> String {
+        let mut parts = vec![];
+        for decl in &self.decls {
+            let (start, end) = (decl.start as usize, (decl.start + decl.width) as usize);
+            if end > packet.len() {
+                break;
+            }
+            let value = packet[start..end]
+                .iter()
+                .fold(0 as IntValue, |acc, &bit| (acc << 1) | bit as IntValue);
+            parts.push(format!("{}={}", decl.name, value));
+        }
+        for (i, &bit) in packet.iter().enumerate().skip(self.num_bits() as usize) {
+            parts.push(format!("x{}={}", i, bit as u8));
+        }
+        if parts.is_empty() {
+            return "()".to_string();
+        }
+        parts.join(",")
+    }
+}
+
+impl fmt::Display for FieldDecl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "field {} : {};", self.name, self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let mut fields = Fields::new();
+        fields.declare("sw", 4).unwrap();
+        fields.declare("up", 1).unwrap();
+        fields.declare("dst", 32).unwrap();
+        assert_eq!(fields.get("up").map(|d| (d.start, d.width)), Some((4, 1)));
+        assert_eq!(fields.get("dst").map(|d| (d.start, d.width)), Some((5, 32)));
+        assert_eq!(fields.num_bits(), 37);
+        assert_eq!(fields.find(4, 1).map(|d| d.name.as_str()), Some("up"));
+        assert_eq!(fields.find(5, 4), None);
+        assert!(fields.get("pt").is_none());
+        assert!(fields.declare("sw", 2).is_err(), "Duplicate field");
+        assert!(fields.declare("pt", 0).is_err(), "Empty field");
+        assert!(fields.declare("pt", 65).is_err(), "Field too wide");
+        for name in ["x3", "dup", "T", "end", "a b", "", "X", "sw2 "] {
+            assert!(fields.declare(name, 1).is_err(), "Invalid name {:?}", name);
+        }
+        assert!(fields.declare("x", 1).is_ok());
+        assert!(fields.declare("_x3", 1).is_ok());
+    }
+
+    #[test]
+    fn test_format_packet() {
+        let mut fields = Fields::new();
+        assert_eq!(fields.format_packet(&[]), "()");
+        assert_eq!(fields.format_packet(&[true, false]), "x0=1,x1=0");
+        fields.declare("sw", 3).unwrap();
+        fields.declare("up", 1).unwrap();
+        assert_eq!(
+            fields.format_packet(&[true, false, true, false, true]),
+            "sw=5,up=0,x4=1"
+        );
+    }
+}
@@ -739,11 +739,15 @@ pub fn gen_leq(ax_depth: usize, expr_depth: usize, num_fields: u32) -> (Exp, Exp
 #[cfg(test)]
 mod tests {
     use crate::aut::Aut;
+    use crate::parser::parse_expressions;
 
     use super::*;
     use rand::rngs::StdRng;
     use rand::SeedableRng;
 
+    /// How many trials the fuzzers run between garbage collections of their automaton
+    const GC_INTERVAL: usize = 100;
+
     #[test]
     fn print_random_genax() {
         let ax_depth = 2;
@@ -756,6 +760,23 @@ mod tests {
         }
     }
 
+    /// Printed expressions parse back to the same expression
+    #[test]
+    fn print_round_trip() {
+        for depth in 0..5 {
+            for _ in 0..200 {
+                let e = gen_random_expr(3, depth);
+                let printed = e.to_string();
+                assert_eq!(parse_expressions(&printed), Ok(vec![e]), "{}", printed);
+            }
+        }
+        for _ in 0..100 {
+            let (e1, e2) = genax(2, 1, 3);
+            let printed = format!("{}\n{}", e1, e2);
+            assert_eq!(parse_expressions(&printed), Ok(vec![e1, e2]), "{}", printed);
+        }
+    }
+
     /// Generates pairs of non-equivalent terms and prints them to stdout
     #[test]
     fn print_random_non_equivalent_terms() {
@@ -771,8 +792,8 @@ mod tests {
                 let xor = Expr::xor(e1.clone(), e2.clone());
                 let mut aut = Aut::new(num_fields);
                 let state = aut.expr_to_state(&xor);
-                if !aut.is_empty(state) {
-                    println!("{:?} != {:?}", e1, e2);
+                if let Some(trace) = aut.witness(state) {
+                    println!("{:?} != {:?} (counterexample: {})", e1, e2, trace);
                 }
             }
         }
@@ -794,6 +815,9 @@ mod tests {
         let max_trials = 10000;
         let mut num_trials = 0;
 
+        // One automaton for all trials, collected every `GC_INTERVAL` trials like a long-running process
+        let mut aut = Aut::new(num_fields);
+
         // For each `n`, test whether the emptiness check
         // passes for `max_trials` rounds
         for n in 0..=ax_depth {
@@ -803,13 +827,23 @@ mod tests {
                 println!("Checking xor of\n  {}\n   ===\n  {}\n", e1, e2);
                 let xor = Expr::xor(e1.clone(), e2.clone());
                 println!("XOR result = {}\n", xor);
-                let mut aut = Aut::new(num_fields);
                 let state = aut.expr_to_state(&xor);
-                if aut.is_empty(state) {
-                    println!("Success!\n");
-                    num_trials += 1;
-                } else {
-                    assert!(false, "Failure!\n");
+                match aut.witness(state) {
+                    None => {
+                        println!("Success!\n");
+                        num_trials += 1;
+                    }
+                    Some(trace) => {
+                        panic!("Failure! Counterexample: {}\n", trace);
+                    }
+                }
+                // The direct equivalence check must agree with the xor automaton
+                let (s1, s2) = (aut.expr_to_state(&e1), aut.expr_to_state(&e2));
+                if let Some(trace) = aut.equiv_counterexample(s1, s2) {
+                    panic!("Failure! Equivalence counterexample: {}\n", trace);
+                }
+                if num_trials % GC_INTERVAL == 0 {
+                    aut.gc();
                 }
             }
         }
@@ -831,6 +865,8 @@ mod tests {
         let max_trials = 10000;
         let mut num_trials = 0;
 
+        let mut aut = Aut::new(num_fields);
+
         // For each `n`, test whether the emptiness check
         // passes for `max_trials` rounds
         for n in 0..=ax_depth {
@@ -845,17 +881,26 @@ mod tests {
                 let xor = Expr::xor(e1_plus_e2, e2.clone());
 
                 println!("XOR result = {}\n", xor);
-                let mut aut = Aut::new(num_fields);
                 let state = aut.expr_to_state(&xor);
-                if aut.is_empty(state) {
-                    println!("Success!\n");
-                    num_trials += 1;
-                } else {
-                    assert!(
-                        false,
-                        "Failure for e1 <= e2!\n  e1 = {}\n  e2 = {}\n",
-                        e1, e2
-                    );
+                match aut.witness(state) {
+                    None => {
+                        println!("Success!\n");
+                        num_trials += 1;
+                    }
+                    Some(trace) => {
+                        panic!(
+                            "Failure for e1 <= e2!\n  e1 = {}\n  e2 = {}\n  counterexample: {}\n",
+                            e1, e2, trace
+                        );
+                    }
+                }
+                // The direct inclusion check must agree with the difference automaton
+                let (s1, s2) = (aut.expr_to_state(&e1), aut.expr_to_state(&e2));
+                if let Some(trace) = aut.includes_counterexample(s1, s2) {
+                    panic!("Failure for e1 <= e2! Inclusion counterexample: {}\n", trace);
+                }
+                if num_trials % GC_INTERVAL == 0 {
+                    aut.gc();
                 }
             }
         }
@@ -940,6 +985,8 @@ mod tests {
             println!("XOR result = {}\n", xor);
             let mut aut = Aut::new(num_fields);
             let state = aut.expr_to_state(&xor);
+            let (s1, s2) = (aut.expr_to_state(&e1), aut.expr_to_state(&e2));
+            assert!(!aut.equiv(s1, s2), "Expected {} and {} to be inequivalent", e1, e2);
             if !aut.is_empty(state) {
                 println!("Success!\n");
             } else {
@@ -0,0 +1,551 @@
+// JSON import and export of expressions and .k2 programs, for tools that generate policies.
+//
+// A program is `{"version": 1, "fields": [{"name": "sw", "width": 4}], "stmts": [...]}`, where a
+// statement is `{"kind": "expr", "expr": e}` or `{"kind": "check", "relation": "==", "left": e1,
+// "right": e2}` (with the relations `==`, `<=` and `!=`). An expression is an object tagged by
+// `op`:
+// - `zero`, `one`, `top`, `dup` and `end` without arguments
+// - `test` and `assign` with a `field` (a bit index or the name of a declared field), a `width`
+//   for ranges of bits (1 by default, and taken from the declaration for a named field) and a
+//   `value`
+// - `union`, `intersect`, `xor` and `sequence` with a list of `args`, folded to the left; an
+//   empty list is the unit of the operator (`0`, `T`, `0` and `1`)
+// - `difference`, `until` and `since` with a `left` and a `right` operand
+// - `complement`, `star`, `next`, `yesterday` and `rev` with an `expr`
+// - `diamond` and `box` with a `path` and a `body`
+// - `proj` with a list of `fields` (bit indices or names of declared fields) and an `expr`
+//
+// Incompatible changes to the schema bump `VERSION`, and files of other versions are rejected.
+
+use crate::expr::{Exp, Expr};
+use crate::fields::Fields;
+use crate::parser::{CheckOp, Program, Stmt};
+use crate::pre::{Field, IntValue};
+use serde::{Deserialize, Serialize};
+
+/// The version of the JSON schema
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonProgram {
+    version: u32,
+    #[serde(default)]
+    fields: Vec<JsonField>,
+    stmts: Vec<JsonStmt>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonField {
+    name: String,
+    width: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum JsonStmt {
+    Expr {
+        expr: JsonExpr,
+    },
+    Check {
+        relation: JsonRelation,
+        left: JsonExpr,
+        right: JsonExpr,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum JsonRelation {
+    #[serde(rename = "==")]
+    Equiv,
+    #[serde(rename = "<=")]
+    Includes,
+    #[serde(rename = "!=")]
+    NotEquiv,
+}
+
+/// A field of a test, an assignment or a projection: a bit index or a declared name
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonFieldRef {
+    Bit(Field),
+    Name(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+enum JsonExpr {
+    // Struct variants, so that unknown fields are rejected as for the others
+    Zero {},
+    One {},
+    Top {},
+    Dup {},
+    End {},
+    Test {
+        field: JsonFieldRef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        width: Option<u32>,
+        value: IntValue,
+    },
+    Assign {
+        field: JsonFieldRef,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        width: Option<u32>,
+        value: IntValue,
+    },
+    Union {
+        args: Vec<JsonExpr>,
+    },
+    Intersect {
+        args: Vec<JsonExpr>,
+    },
+    Xor {
+        args: Vec<JsonExpr>,
+    },
+    Sequence {
+        args: Vec<JsonExpr>,
+    },
+    Difference {
+        left: Box<JsonExpr>,
+        right: Box<JsonExpr>,
+    },
+    Until {
+        left: Box<JsonExpr>,
+        right: Box<JsonExpr>,
+    },
+    Since {
+        left: Box<JsonExpr>,
+        right: Box<JsonExpr>,
+    },
+    Complement {
+        expr: Box<JsonExpr>,
+    },
+    Star {
+        expr: Box<JsonExpr>,
+    },
+    Next {
+        expr: Box<JsonExpr>,
+    },
+    Yesterday {
+        expr: Box<JsonExpr>,
+    },
+    Rev {
+        expr: Box<JsonExpr>,
+    },
+    Diamond {
+        path: Box<JsonExpr>,
+        body: Box<JsonExpr>,
+    },
+    #[serde(rename = "box")]
+    BoxModality {
+        path: Box<JsonExpr>,
+        body: Box<JsonExpr>,
+    },
+    Proj {
+        fields: Vec<JsonFieldRef>,
+        expr: Box<JsonExpr>,
+    },
+}
+
+// --- Export ---
+
+// The operands of a left-nested chain `((e1 op e2) op e3) ...`, where `split` takes apart an `op`
+fn chain(e1: &Expr, e2: &Expr, fields: &Fields, split: fn(&Expr) -> Option<(&Expr, &Expr)>) -> Vec<JsonExpr> {
+    let mut args = vec![export_expr(e2, fields)];
+    let mut left = e1;
+    while let Some((e1, e2)) = split(left) {
+        args.push(export_expr(e2, fields));
+        left = e1;
+    }
+    args.push(export_expr(left, fields));
+    args.reverse();
+    args
+}
+
+// The field of the bits `start..start+width`, by name if it is declared
+fn export_field(start: Field, width: u32, fields: &Fields) -> (JsonFieldRef, Option<u32>) {
+    match fields.find(start, width) {
+        Some(decl) => (JsonFieldRef::Name(decl.name.clone()), None),
+        None if width == 1 => (JsonFieldRef::Bit(start), None),
+        None => (JsonFieldRef::Bit(start), Some(width)),
+    }
+}
+
+fn export_expr(expr: &Expr, fields: &Fields) -> JsonExpr {
+    let export = |e: &Expr| Box::new(export_expr(e, fields));
+    match expr {
+        Expr::Zero => JsonExpr::Zero {},
+        Expr::One => JsonExpr::One {},
+        Expr::Top => JsonExpr::Top {},
+        Expr::Dup => JsonExpr::Dup {},
+        Expr::End => JsonExpr::End {},
+        Expr::Test(field, value) => {
+            let (field, width) = export_field(*field, 1, fields);
+            JsonExpr::Test { field, width, value: *value as IntValue }
+        }
+        Expr::Assign(field, value) => {
+            let (field, width) = export_field(*field, 1, fields);
+            JsonExpr::Assign { field, width, value: *value as IntValue }
+        }
+        Expr::TestInt(start, width, value) => {
+            let (field, width) = export_field(*start, *width, fields);
+            JsonExpr::Test { field, width, value: *value }
+        }
+        Expr::AssignInt(start, width, value) => {
+            let (field, width) = export_field(*start, *width, fields);
+            JsonExpr::Assign { field, width, value: *value }
+        }
+        Expr::Union(e1, e2) => JsonExpr::Union {
+            args: chain(e1, e2, fields, |e| match e {
+                Expr::Union(e1, e2) => Some((e1, e2)),
+                _ => None,
+            }),
+        },
+        Expr::Intersect(e1, e2) => JsonExpr::Intersect {
+            args: chain(e1, e2, fields, |e| match e {
+                Expr::Intersect(e1, e2) => Some((e1, e2)),
+                _ => None,
+            }),
+        },
+        Expr::Xor(e1, e2) => JsonExpr::Xor {
+            args: chain(e1, e2, fields, |e| match e {
+                Expr::Xor(e1, e2) => Some((e1, e2)),
+                _ => None,
+            }),
+        },
+        Expr::Sequence(e1, e2) => JsonExpr::Sequence {
+            args: chain(e1, e2, fields, |e| match e {
+                Expr::Sequence(e1, e2) => Some((e1, e2)),
+                _ => None,
+            }),
+        },
+        Expr::Difference(e1, e2) => JsonExpr::Difference { left: export(e1), right: export(e2) },
+        Expr::LtlUntil(e1, e2) => JsonExpr::Until { left: export(e1), right: export(e2) },
+        Expr::LtlSince(e1, e2) => JsonExpr::Since { left: export(e1), right: export(e2) },
+        Expr::Complement(e) => JsonExpr::Complement { expr: export(e) },
+        Expr::Star(e) => JsonExpr::Star { expr: export(e) },
+        Expr::LtlNext(e) => JsonExpr::Next { expr: export(e) },
+        Expr::LtlYesterday(e) => JsonExpr::Yesterday { expr: export(e) },
+        Expr::Rev(e) => JsonExpr::Rev { expr: export(e) },
+        Expr::LdlDiamond(e1, e2) => JsonExpr::Diamond { path: export(e1), body: export(e2) },
+        Expr::LdlBox(e1, e2) => JsonExpr::BoxModality { path: export(e1), body: export(e2) },
+        Expr::Proj(kept, e) => {
+            // Name a declared field if all of its bits are kept, as in `ExprDisplay`
+            let mut refs = vec![];
+            let mut named = vec![];
+            for decl in fields.decls() {
+                let bits: Vec<Field> = (decl.start..decl.start + decl.width).collect();
+                if bits.iter().all(|bit| kept.contains(bit)) {
+                    refs.push(JsonFieldRef::Name(decl.name.clone()));
+                    named.extend(bits);
+                }
+            }
+            for field in kept.iter().filter(|field| !named.contains(field)) {
+                refs.push(JsonFieldRef::Bit(*field));
+            }
+            JsonExpr::Proj { fields: refs, expr: export(e) }
+        }
+    }
+}
+
+/// Exports an expression as JSON, naming the declared `fields`
+pub fn expr_to_json(expr: &Expr, fields: &Fields) -> String {
+    serde_json::to_string_pretty(&export_expr(expr, fields)).unwrap()
+}
+
+/// Exports a program as a JSON document of the current `VERSION`
+pub fn program_to_json(program: &Program) -> String {
+    let fields = &program.fields;
+    let json = JsonProgram {
+        version: VERSION,
+        fields: fields
+            .decls()
+            .iter()
+            .map(|decl| JsonField { name: decl.name.clone(), width: decl.width })
+            .collect(),
+        stmts: program
+            .stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Expr(e) => JsonStmt::Expr { expr: export_expr(e, fields) },
+                Stmt::Check(op, e1, e2) => JsonStmt::Check {
+                    relation: match op {
+                        CheckOp::Equiv => JsonRelation::Equiv,
+                        CheckOp::Includes => JsonRelation::Includes,
+                        CheckOp::NotEquiv => JsonRelation::NotEquiv,
+                    },
+                    left: export_expr(e1, fields),
+                    right: export_expr(e2, fields),
+                },
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&json).unwrap()
+}
+
+// --- Import ---
+
+// Resolves a field reference to its bits `start..start+width`
+fn import_field(field: &JsonFieldRef, width: Option<u32>, fields: &Fields) -> Result<(Field, u32), String> {
+    match field {
+        JsonFieldRef::Bit(start) => match width.unwrap_or(1) {
+            0 => Err(format!("Field x{} has width 0", start)),
+            width if width > IntValue::BITS => Err(format!(
+                "Field x{} must be at most {} bits wide, found {}",
+                start,
+                IntValue::BITS,
+                width
+            )),
+            // The end of the range is a field index too, so it must fit in a `Field`
+            width if start.checked_add(width).is_none() => {
+                Err(format!("Field x{} with width {} is out of range", start, width))
+            }
+            width => Ok((*start, width)),
+        },
+        JsonFieldRef::Name(name) => {
+            let decl = fields
+                .get(name)
+                .ok_or_else(|| format!("Undeclared field `{}`", name))?;
+            match width {
+                Some(width) if width != decl.width => Err(format!(
+                    "Field `{}` has {} bits, found width {}",
+                    name, decl.width, width
+                )),
+                _ => Ok((decl.start, decl.width)),
+            }
+        }
+    }
+}
+
+// The test or the assignment of `value` to a field, checking that the value fits
+fn import_field_op(
+    field: &JsonFieldRef,
+    width: Option<u32>,
+    value: IntValue,
+    fields: &Fields,
+    assign: bool,
+) -> Result<Exp, String> {
+    let (start, width) = import_field(field, width, fields)?;
+    if width < IntValue::BITS && value >> width != 0 {
+        return Err(format!("Value {} does not fit in {} bits", value, width));
+    }
+    Ok(match (assign, width) {
+        (false, 1) => Expr::test(start, value == 1),
+        (true, 1) => Expr::assign(start, value == 1),
+        (false, _) => Expr::test_int(start, width, value),
+        (true, _) => Expr::assign_int(start, width, value),
+    })
+}
+
+// Folds the operands of an n-ary operator to the left, starting from its unit
+fn fold(args: &[JsonExpr], fields: &Fields, unit: fn() -> Exp, op: fn(Exp, Exp) -> Exp) -> Result<Exp, String> {
+    let mut args = args.iter();
+    let Some(first) = args.next() else {
+        return Ok(unit());
+    };
+    let mut expr = import_expr(first, fields)?;
+    for arg in args {
+        expr = op(expr, import_expr(arg, fields)?);
+    }
+    Ok(expr)
+}
+
+// Rejects past operators where the parser does (see `parser::no_past`)
+fn no_past(expr: Exp, place: &str) -> Result<Exp, String> {
+    if expr.has_past() {
+        return Err(format!("Past operators (Y, Z, S, O, H) cannot be used {}", place));
+    }
+    Ok(expr)
+}
+
+fn import_expr(json: &JsonExpr, fields: &Fields) -> Result<Exp, String> {
+    let import = |e: &JsonExpr| import_expr(e, fields);
+    Ok(match json {
+        JsonExpr::Zero {} => Expr::zero(),
+        JsonExpr::One {} => Expr::one(),
+        JsonExpr::Top {} => Expr::top(),
+        JsonExpr::Dup {} => Expr::dup(),
+        JsonExpr::End {} => Expr::end(),
+        JsonExpr::Test { field, width, value } => import_field_op(field, *width, *value, fields, false)?,
+        JsonExpr::Assign { field, width, value } => import_field_op(field, *width, *value, fields, true)?,
+        JsonExpr::Union { args } => fold(args, fields, Expr::zero, Expr::union)?,
+        JsonExpr::Intersect { args } => fold(args, fields, Expr::top, Expr::intersect)?,
+        JsonExpr::Xor { args } => fold(args, fields, Expr::zero, Expr::xor)?,
+        JsonExpr::Sequence { args } => {
+            let mut args = args.iter();
+            let mut expr = match args.next() {
+                Some(first) => import(first)?,
+                None => Expr::one(),
+            };
+            for arg in args {
+                let right = no_past(import(arg)?, "on the right of ';'")?;
+                expr = Expr::sequence(expr, right);
+            }
+            expr
+        }
+        JsonExpr::Difference { left, right } => Expr::difference(import(left)?, import(right)?),
+        JsonExpr::Until { left, right } => Expr::ltl_until(import(left)?, import(right)?),
+        JsonExpr::Since { left, right } => Expr::ltl_since(import(left)?, import(right)?),
+        JsonExpr::Complement { expr } => Expr::complement(import(expr)?),
+        JsonExpr::Star { expr } => Expr::star(no_past(import(expr)?, "under '*'")?),
+        JsonExpr::Next { expr } => Expr::ltl_next(import(expr)?),
+        JsonExpr::Yesterday { expr } => Expr::ltl_yesterday(import(expr)?),
+        JsonExpr::Rev { expr } => Expr::rev(import(expr)?),
+        JsonExpr::Diamond { path, body } => {
+            Expr::ldl_diamond(import(path)?, no_past(import(body)?, "after an LDL modality")?)
+        }
+        JsonExpr::BoxModality { path, body } => {
+            Expr::ldl_box(import(path)?, no_past(import(body)?, "after an LDL modality")?)
+        }
+        JsonExpr::Proj { fields: refs, expr } => {
+            let mut kept = vec![];
+            for field in refs {
+                let (start, width) = import_field(field, None, fields)?;
+                kept.extend(start..start + width);
+            }
+            Expr::proj(kept, no_past(import(expr)?, "under 'proj'")?)
+        }
+    })
+}
+
+/// Imports an expression from JSON, resolving field names in the declared `fields`
+pub fn expr_from_json(input: &str, fields: &Fields) -> Result<Exp, String> {
+    let json: JsonExpr = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    import_expr(&json, fields)
+}
+
+/// Imports a program from a JSON document of the current `VERSION`
+pub fn program_from_json(input: &str) -> Result<Program, String> {
+    // Check the version first, since other versions may not match the rest of the schema
+    let document: serde_json::Value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    match document.get("version").and_then(|version| version.as_u64()) {
+        Some(version) if version == VERSION as u64 => {}
+        Some(version) => {
+            return Err(format!("Unsupported JSON version {}, expected {}", version, VERSION));
+        }
+        None => return Err("Missing the JSON version".to_string()),
+    }
+    let json: JsonProgram = serde_json::from_value(document).map_err(|e| e.to_string())?;
+    let mut fields = Fields::new();
+    for field in &json.fields {
+        fields.declare(&field.name, field.width)?;
+    }
+    let mut stmts = vec![];
+    for stmt in &json.stmts {
+        stmts.push(match stmt {
+            JsonStmt::Expr { expr } => Stmt::Expr(import_expr(expr, &fields)?),
+            JsonStmt::Check { relation, left, right } => {
+                let op = match relation {
+                    JsonRelation::Equiv => CheckOp::Equiv,
+                    JsonRelation::Includes => CheckOp::Includes,
+                    JsonRelation::NotEquiv => CheckOp::NotEquiv,
+                };
+                Stmt::Check(op, import_expr(left, &fields)?, import_expr(right, &fields)?)
+            }
+        });
+    }
+    Ok(Program { fields, stmts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    #[test]
+    fn test_round_trip() {
+        let program = parse_program(
+            "field sw : 4; field up : 1;
+             let net = (sw == 1 ; sw := 2 + sw == 2 ; sw := 3) ; dup
+             check net* ; sw == 3 <= T
+             check (x5 == 1 ; dup) & !X x[6..8] := 2 ^ end == 0 - dup
+             check Y up == 1 U F (sw == 2 ; T) S rev dup != O end
+             <proj {sw, x5} (x5 := 0 ; dup)*> [up == 0] T",
+        )
+        .unwrap();
+        let json = program_to_json(&program);
+        assert_eq!(program_from_json(&json), Ok(program.clone()));
+        for stmt in &program.stmts {
+            if let Stmt::Expr(expr) = stmt {
+                let json = expr_to_json(expr, &program.fields);
+                assert_eq!(expr_from_json(&json, &program.fields), Ok(expr.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let fields = parse_program("field sw : 4;").unwrap().fields;
+        let expr = parse_program("field sw : 4; sw == 3 ; x4 := 1 ; x[5..7] == 2 + dup + end")
+            .unwrap()
+            .stmts
+            .remove(0);
+        let Stmt::Expr(expr) = expr else { unreachable!() };
+        let json: serde_json::Value = serde_json::from_str(&expr_to_json(&expr, &fields)).unwrap();
+        // Chains of a left associative operator are flattened
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "op": "sequence",
+                "args": [
+                    {"op": "test", "field": "sw", "value": 3},
+                    {"op": "assign", "field": 4, "value": 1},
+                    {"op": "union", "args": [
+                        {"op": "test", "field": 5, "width": 2, "value": 2},
+                        {"op": "dup"},
+                        {"op": "end"}
+                    ]}
+                ]
+            })
+        );
+        assert_eq!(
+            program_to_json(&Program { fields: fields.clone(), stmts: vec![] }).replace(char::is_whitespace, ""),
+            r#"{"version":1,"fields":[{"name":"sw","width":4}],"stmts":[]}"#
+        );
+        // Empty chains are the unit of the operator
+        assert_eq!(expr_from_json(r#"{"op": "union", "args": []}"#, &fields), Ok(Expr::zero()));
+        assert_eq!(expr_from_json(r#"{"op": "sequence", "args": []}"#, &fields), Ok(Expr::one()));
+    }
+
+    #[test]
+    fn test_errors() {
+        let fields = Fields::new();
+        for (input, error) in [
+            (r#"{"op": "test", "field": "sw", "value": 1}"#, "Undeclared field `sw`"),
+            (r#"{"op": "test", "field": 0, "value": 2}"#, "Value 2 does not fit in 1 bits"),
+            (r#"{"op": "assign", "field": 0, "width": 0, "value": 0}"#, "Field x0 has width 0"),
+            (
+                r#"{"op": "test", "field": 0, "width": 70, "value": 1}"#,
+                "Field x0 must be at most 64 bits wide, found 70",
+            ),
+            (
+                r#"{"op": "proj", "fields": [4294967295], "expr": {"op": "dup"}}"#,
+                "Field x4294967295 with width 1 is out of range",
+            ),
+            (
+                r#"{"op": "star", "expr": {"op": "yesterday", "expr": {"op": "dup"}}}"#,
+                "Past operators (Y, Z, S, O, H) cannot be used under '*'",
+            ),
+        ] {
+            assert_eq!(expr_from_json(input, &fields), Err(error.to_string()));
+        }
+        assert!(expr_from_json(r#"{"op": "nope"}"#, &fields).is_err());
+        assert!(expr_from_json(r#"{"op": "dup", "extra": 1}"#, &fields).is_err());
+        assert_eq!(
+            program_from_json(r#"{"version": 2, "stmts": []}"#),
+            Err("Unsupported JSON version 2, expected 1".to_string())
+        );
+        assert_eq!(
+            program_from_json(r#"{"stmts": []}"#),
+            Err("Missing the JSON version".to_string())
+        );
+        assert!(program_from_json(r#"{"version": 1, "fields": [{"name": "a", "width": 1}, {"name": "a", "width": 1}], "stmts": []}"#).is_err());
+        // Field names must print as themselves
+        for name in ["x3", "dup", "T", "a b"] {
+            let input = format!(r#"{{"version": 1, "fields": [{{"name": "{}", "width": 2}}], "stmts": []}}"#, name);
+            let error = program_from_json(&input).unwrap_err();
+            assert!(error.contains("is not a valid field name"), "{}: {}", name, error);
+        }
+    }
+}
@@ -1,7 +1,16 @@
 pub mod aut;
+pub mod elim;
 pub mod expr;
+pub mod fields;
+pub mod json;
+pub mod minimize;
+pub mod nkpl;
+pub mod omega;
 pub mod parser;
 pub mod pre;
+pub mod prune;
 pub mod sp;
 pub mod spp;
+pub mod trace;
+pub mod unproj;
 pub mod viz;
@@ -4,18 +4,29 @@
 
 use clap::{Parser, Subcommand};
 use expr::Expr;
+use fields::Fields;
+use parser::{CheckOp, Stmt};
 use std::fs;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 mod aut;
+mod elim;
 mod expr;
+mod fields;
 mod fuzz;
+mod json;
+mod minimize;
+mod nkpl;
+mod omega;
 mod parser;
 mod pre;
+mod prune;
 mod sp;
 mod spp;
+mod trace;
 mod ui;
+mod unproj;
 mod viz;
 /// KATch2: A symbolic automata toolkit for NetKAT expressions
 #[derive(Parser, Debug)]
@@ -39,6 +50,27 @@ enum Commands {
     Parse {
         /// The file or directory path to parse
         path: PathBuf,
+        /// Interpret the expressions over infinite strings (omega mode)
+        #[arg(long)]
+        omega: bool,
+        /// Read JSON ASTs (.json files) instead of .k2 text
+        #[arg(long)]
+        json: bool,
+        /// Print the parsed file as a JSON AST instead of processing it
+        #[arg(long)]
+        dump_json: bool,
+        /// Also eliminate the dups of each expression and print a packet pair of the relation
+        #[arg(long)]
+        eliminate: bool,
+        /// Also compile the automaton state of each expression into extra packet bits
+        #[arg(long)]
+        unproject: bool,
+    },
+
+    /// Run the checks of a KATch1 NKPL file, such as its fuzz tests
+    Nkpl {
+        /// The .nkpl file to run
+        path: PathBuf,
     },
 }
 
@@ -54,86 +86,326 @@ async fn main() {
                 std::process::exit(1);
             }
         }
-        Commands::Parse { path } => {
+        Commands::Parse { path, omega, json, dump_json, eliminate, unproject } => {
+            let analyses = Analyses { eliminate: *eliminate, unproject: *unproject };
             // Traditional file processing mode
             if !path.exists() {
                 eprintln!("Error: Path \"{}\" does not exist.", path.display());
                 std::process::exit(1);
             }
 
-            if path.is_dir() {
-                process_directory(&path);
+            let failures = if *dump_json {
+                if !path.is_file() {
+                    eprintln!("Error: --dump-json needs a file, not \"{}\".", path.display());
+                    std::process::exit(1);
+                }
+                dump_json_file(path, *json)
+            } else if path.is_dir() {
+                process_directory(path, *omega, *json, analyses)
             } else if path.is_file() {
-                process_file(&path);
+                process_file(path, *omega, *json, analyses)
             } else {
                 eprintln!(
                     "Error: Path \"{}\" is neither a file nor a directory.",
                     path.display()
                 );
                 std::process::exit(1);
+            };
+            if failures > 0 {
+                std::process::exit(1);
+            }
+        }
+        Commands::Nkpl { path } => {
+            if process_nkpl_file(path) > 0 {
+                std::process::exit(1);
             }
         }
     }
 }
 
-fn process_directory(dir_path: &Path) {
+fn process_directory(dir_path: &Path, omega: bool, json: bool, analyses: Analyses) -> usize {
     println!("Processing directory: {}", dir_path.display());
-    let mut found_k2_files = false;
+    let extension = if json { "json" } else { "k2" };
+    let mut found_files = false;
+    let mut failures = 0;
     for entry in WalkDir::new(dir_path).into_iter().filter_map(|e| e.ok()) {
         let path = entry.path();
         if path.is_file() {
             if let Some(ext) = path.extension() {
-                if ext == "k2" {
-                    found_k2_files = true;
-                    process_file(path);
+                if ext == extension {
+                    found_files = true;
+                    failures += process_file(path, omega, json, analyses);
                 }
             }
         }
     }
-    if !found_k2_files {
-        println!("No .k2 files found in directory.");
+    if !found_files {
+        println!("No .{} files found in directory.", extension);
+    }
+    failures
+}
+
+/// Reads a .k2 file, or a JSON AST if `json` is set, rendering the errors for the user
+fn read_program(content: &str, file_path: &Path, json: bool) -> Result<parser::Program, String> {
+    if json {
+        json::program_from_json(content).map_err(|e| format!("  Error reading JSON: {}", e))
+    } else {
+        parser::parse_program(content).map_err(|e| e.render(content, &file_path.display().to_string()))
+    }
+}
+
+/// Prints a parsed file as a JSON AST, returning 1 if it could not be read or parsed
+fn dump_json_file(file_path: &Path, json: bool) -> usize {
+    let content = match fs::read_to_string(file_path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("  Error reading file: {}", e);
+            return 1;
+        }
+    };
+    match read_program(&content, file_path, json) {
+        Ok(program) => {
+            println!("{}", json::program_to_json(&program));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
     }
 }
 
-fn process_file(file_path: &Path) {
+/// Processes all statements in a file, returning the number of failures
+/// (failed checks, or 1 if the file could not be read or parsed)
+fn process_file(file_path: &Path, omega: bool, json: bool, analyses: Analyses) -> usize {
     println!("--- Processing file: {} ---", file_path.display());
+    let mut failures = 0;
     match fs::read_to_string(file_path) {
         Ok(content) => {
-            match parser::parse_expressions(&content) {
-                Ok(expressions) => {
-                    if expressions.is_empty() {
-                        println!("No expressions found or parsed.");
+            match read_program(&content, file_path, json) {
+                Ok(program) => {
+                    let statements = program.stmts;
+                    let fields = program.fields;
+                    if !fields.is_empty() {
+                        println!("Fields:");
+                        for decl in fields.decls() {
+                            println!("  {} (bits {}..{})", decl, decl.start, decl.start + decl.width);
+                        }
+                    }
+                    if statements.is_empty() {
+                        println!("No statements found or parsed.");
                     } else {
-                        println!("Parsed Expressions:");
-                        for (i, expr) in expressions.iter().enumerate() {
-                            println!("  {}: {:?}", i + 1, expr);
+                        println!("Parsed Statements:");
+                        for (i, stmt) in statements.iter().enumerate() {
+                            println!("  {}: {:?}", i + 1, stmt);
                             // Potentially print a more user-friendly format later
                             // println!("  {}: {}", i + 1, expr);
                         }
-                        for expr in &expressions {
-                            process_expression(expr);
+                        let mut passed = 0;
+                        for stmt in &statements {
+                            match stmt {
+                                Stmt::Expr(expr) if omega => process_omega_expression(expr, &fields),
+                                Stmt::Expr(expr) => process_expression(expr, &fields, analyses),
+                                Stmt::Check(op, e1, e2) => {
+                                    let ok = if omega {
+                                        process_omega_check(*op, e1, e2, &fields)
+                                    } else {
+                                        process_check(*op, e1, e2, &fields)
+                                    };
+                                    if ok {
+                                        passed += 1;
+                                    } else {
+                                        failures += 1;
+                                    }
+                                }
+                            }
+                        }
+                        if passed + failures > 0 {
+                            println!("Checks: {} passed, {} failed", passed, failures);
                         }
                     }
                 }
                 Err(e) => {
-                    eprintln!("  Error parsing file: {}", e);
+                    eprintln!("{}", e);
+                    failures += 1;
                 }
             }
         }
         Err(e) => {
             eprintln!("  Error reading file: {}", e);
+            failures += 1;
         }
     }
     println!("-------------------------------");
+    failures
 }
 
-fn process_expression(expr: &Box<Expr>) {
+/// Runs the checks of an NKPL file, returning the number of failures
+/// (failed checks, or 1 if the file could not be read or parsed)
+fn process_nkpl_file(file_path: &Path) -> usize {
+    println!("--- Processing NKPL file: {} ---", file_path.display());
+    let content = match fs::read_to_string(file_path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("  Error reading file: {}", e);
+            return 1;
+        }
+    };
+    let program = match nkpl::parse_nkpl(&content) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e.render(&content, &file_path.display().to_string()));
+            return 1;
+        }
+    };
+    let start = std::time::Instant::now();
+    let (mut passed, mut failures) = (0, 0);
+    for stmt in &program.stmts {
+        if let Stmt::Check(op, e1, e2) = stmt {
+            if process_check(*op, e1, e2, &program.fields) {
+                passed += 1;
+            } else {
+                failures += 1;
+            }
+        }
+    }
+    println!(
+        "Checks: {} passed, {} failed in {:.2?}",
+        passed,
+        failures,
+        start.elapsed()
+    );
+    failures
+}
+
+/// The optional analyses that `katch2 parse` runs on each expression
+#[derive(Debug, Clone, Copy)]
+struct Analyses {
+    eliminate: bool,
+    unproject: bool,
+}
+
+fn process_expression(expr: &Box<Expr>, fields: &Fields, analyses: Analyses) {
     // Create an automaton from the expression
-    let mut aut = aut::Aut::new(expr.num_fields());
+    let mut aut = aut::Aut::new(expr.num_fields().max(fields.num_bits()));
     let state = aut.expr_to_state(expr);
     println!("State: {}", state);
     let delta = aut.delta(state);
     println!("Delta: {:?}", delta);
     let epsilon = aut.epsilon(state);
     println!("Epsilon: {:?}", epsilon);
+    // Explore once, and minimize, eliminate and unproject the same explicit automaton
+    let explicit = aut.explore(state);
+    let minimal = minimize::minimize(&explicit, aut.spp_store_mut());
+    println!("States: {} ({} after minimization)", explicit.num_states(), minimal.num_states());
+    if analyses.eliminate {
+        let relation = elim::eliminate(&explicit, aut.spp_store_mut());
+        match aut.spp_store_mut().pick(relation) {
+            Some((input, output)) => println!(
+                "Relation (dups eliminated): e.g. {} -> {}",
+                fields.format_packet(&input),
+                fields.format_packet(&output)
+            ),
+            None => println!("Relation (dups eliminated): empty"),
+        }
+    }
+    if analyses.unproject {
+        let unproj = unproj::unproject(&explicit, aut.spp_store());
+        let end = unproj.start + unproj.bits;
+        match unproj.bits {
+            0 => println!("Unprojected: a single state, which needs no state bits"),
+            1 => println!("Unprojected: {} states in the state bit x{}", explicit.num_states(), unproj.start),
+            _ => println!("Unprojected: {} states in the state bits x[{}..{}]", explicit.num_states(), unproj.start, end),
+        }
+    }
+    match aut.shortest_witness(state) {
+        Some(trace) => println!("Shortest trace: {}", trace.display(fields)),
+        None => println!("Shortest trace: none (the expression is empty)"),
+    }
+}
+
+/// Decides the omega emptiness of an expression and prints a lasso if it is non-empty
+fn process_omega_expression(expr: &Expr, fields: &Fields) {
+    if expr.has_past() {
+        eprintln!("  Past operators are not supported in omega mode");
+        return;
+    }
+    let mut aut = aut::Aut::new(expr.num_fields().max(fields.num_bits()));
+    let state = aut.expr_to_state(expr);
+    let omega = aut.explore_omega(state);
+    println!("Omega states: {}", omega.num_states());
+    match omega.lasso(aut.spp_store_mut()) {
+        Some(lasso) => println!("Infinite trace: {}", lasso.display(fields)),
+        None => println!("Infinite trace: none (the expression is empty)"),
+    }
+}
+
+/// Decides a check statement over infinite strings and prints the outcome,
+/// returning whether it passed
+fn process_omega_check(op: CheckOp, e1: &Expr, e2: &Expr, fields: &Fields) -> bool {
+    if e1.has_past() || e2.has_past() {
+        eprintln!("  Past operators are not supported in omega mode");
+        return false;
+    }
+    let num_fields = e1.num_fields().max(e2.num_fields()).max(fields.num_bits());
+    let mut aut = aut::Aut::new(num_fields);
+    let s1 = aut.expr_to_state(e1);
+    let s2 = aut.expr_to_state(e2);
+    let witness = match op {
+        CheckOp::Equiv | CheckOp::NotEquiv => aut.omega_equiv_counterexample(s1, s2),
+        CheckOp::Includes => aut.omega_includes_counterexample(s1, s2),
+    };
+    let passed = match op {
+        CheckOp::Equiv | CheckOp::Includes => witness.is_none(),
+        CheckOp::NotEquiv => witness.is_some(),
+    };
+    let status = if passed { "PASS" } else { "FAIL" };
+    println!(
+        "check {} {} {} (omega): {}",
+        e1.display(fields),
+        op,
+        e2.display(fields),
+        status
+    );
+    if let Some(lasso) = witness {
+        println!("  trace: {}", lasso.display(fields));
+    }
+    passed
+}
+
+/// Decides a check statement and prints the outcome, returning whether it passed
+fn process_check(op: CheckOp, e1: &Expr, e2: &Expr, fields: &Fields) -> bool {
+    // Both checks are decided directly on the pair of states, without building
+    // the xor or difference automaton
+    let num_fields = e1.num_fields().max(e2.num_fields()).max(fields.num_bits());
+    let mut aut = aut::Aut::new(num_fields);
+    let witness = match op {
+        CheckOp::Equiv | CheckOp::NotEquiv => {
+            let s1 = aut.expr_to_state(e1);
+            let s2 = aut.expr_to_state(e2);
+            aut.equiv_counterexample(s1, s2)
+        }
+        CheckOp::Includes => {
+            let s1 = aut.expr_to_state(e1);
+            let s2 = aut.expr_to_state(e2);
+            aut.includes_counterexample(s1, s2)
+        }
+    };
+    let passed = match op {
+        CheckOp::Equiv | CheckOp::Includes => witness.is_none(),
+        CheckOp::NotEquiv => witness.is_some(),
+    };
+    let status = if passed { "PASS" } else { "FAIL" };
+    println!(
+        "check {} {} {}: {}",
+        e1.display(fields),
+        op,
+        e2.display(fields),
+        status
+    );
+    if let Some(trace) = witness {
+        // For `==` and `<=` this is a counterexample, for `!=` it is a distinguishing trace
+        println!("  trace: {}", trace.display(fields));
+    }
+    passed
 }
@@ -0,0 +1,218 @@
+// Minimization of explicit automata by symbolic partition refinement.
+//
+// We start from the partition of the states by their epsilon SPP, and repeatedly split
+// the blocks: two states stay together only if, for every block, the union of the SPPs
+// of their transitions into that block is the same. Since SPPs are hash-consed, these
+// comparisons are exact.
+//
+// On their own, the comparisons include the packet pairs that can never occur at a state,
+// so states that only agree on the packets that can actually reach them would be kept apart.
+// We therefore alternate the quotient with pruning (see `prune`), which restricts the SPPs
+// of each state to its reachable packets. Pruning alone is not enough either: states with
+// the same SPPs but different reachable packets would no longer agree after pruning, so we
+// quotient before the first pruning, and repeat until the number of states is stable.
+
+use crate::aut::{ExplicitAut, ST};
+use crate::prune::prune;
+use crate::spp::{SPP, SPPstore};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// Computes the block of each state of the coarsest stable partition
+fn refine(explicit: &ExplicitAut, store: &mut SPPstore) -> Vec<usize> {
+    let mut blocks = HashMap::new();
+    let mut block: Vec<usize> = explicit
+        .epsilons
+        .iter()
+        .map(|eps| {
+            let next = blocks.len();
+            *blocks.entry(*eps).or_insert(next)
+        })
+        .collect();
+    let mut num_blocks = blocks.len();
+    loop {
+        let mut signatures: HashMap<(usize, Vec<(usize, SPP)>), usize> = HashMap::new();
+        let mut refined = vec![];
+        for (state, st) in explicit.transitions.iter().enumerate() {
+            let outgoing = block_transitions(st, &block, store);
+            let next = signatures.len();
+            refined.push(*signatures.entry((block[state], outgoing)).or_insert(next));
+        }
+        block = refined;
+        if signatures.len() == num_blocks {
+            return block;
+        }
+        num_blocks = signatures.len();
+    }
+}
+
+/// Groups the transitions of a state by the block of their target, sorted by block
+fn block_transitions(st: &ST, block: &[usize], store: &mut SPPstore) -> Vec<(usize, SPP)> {
+    let mut grouped: HashMap<usize, SPP> = HashMap::new();
+    for (&target, &spp) in st.get_transitions() {
+        let entry = grouped.entry(block[target]).or_insert(store.zero);
+        *entry = store.union(*entry, spp);
+    }
+    let mut grouped: Vec<_> = grouped.into_iter().collect();
+    grouped.sort();
+    grouped
+}
+
+/// Returns the minimal automaton with the same behaviour as `explicit`, restricted to the
+/// packet pairs that occur in some accepted string. An empty automaton minimizes to no states.
+///
+/// The states are numbered in breadth-first order, visiting the targets of a state by
+/// increasing SPP, so minimizing two automata built in the same `Aut` yields equal
+/// transitions and epsilons iff their minimal automata are isomorphic (see `same_structure`).
+pub fn minimize(explicit: &ExplicitAut, store: &mut SPPstore) -> ExplicitAut {
+    let mut current = quotient(explicit, store);
+    loop {
+        let pruned = prune(&current, store);
+        let next = quotient(&pruned, store);
+        if next.num_states() == current.num_states() {
+            return next;
+        }
+        current = next;
+    }
+}
+
+/// Returns the quotient of `explicit` by the coarsest stable partition, numbered as in `minimize`.
+/// Each state is represented by its first state in `explicit`, whose `Aut` state and label it keeps.
+fn quotient(explicit: &ExplicitAut, store: &mut SPPstore) -> ExplicitAut {
+    if explicit.num_states() == 0 {
+        return explicit.clone();
+    }
+    let block = refine(explicit, store);
+    let num_blocks = block.iter().max().unwrap() + 1;
+    let mut representative = vec![usize::MAX; num_blocks];
+    for (state, &b) in block.iter().enumerate().rev() {
+        representative[b] = state;
+    }
+
+    // Number the blocks in a deterministic breadth-first order from the root's block
+    let mut number: HashMap<usize, usize> = HashMap::from([(block[0], 0)]);
+    let mut order = vec![block[0]];
+    let mut outgoing = vec![];
+    let mut i = 0;
+    while i < order.len() {
+        let rep = representative[order[i]];
+        let mut targets: Vec<(SPP, usize)> =
+            block_transitions(&explicit.transitions[rep], &block, store)
+                .into_iter()
+                .map(|(b, spp)| (spp, b))
+                .collect();
+        targets.sort();
+        for &(_, b) in &targets {
+            if let Entry::Vacant(entry) = number.entry(b) {
+                entry.insert(order.len());
+                order.push(b);
+            }
+        }
+        outgoing.push(targets);
+        i += 1;
+    }
+
+    let transitions = outgoing
+        .into_iter()
+        .map(|targets| {
+            ST::new(
+                targets
+                    .into_iter()
+                    .map(|(spp, b)| (number[&b], spp))
+                    .collect(),
+            )
+        })
+        .collect();
+    let states = order
+        .iter()
+        .map(|&b| explicit.states[representative[b]])
+        .collect();
+    let labels = order
+        .iter()
+        .map(|&b| explicit.labels[representative[b]].clone())
+        .collect();
+    let epsilons = order
+        .iter()
+        .map(|&b| explicit.epsilons[representative[b]])
+        .collect();
+    ExplicitAut {
+        states,
+        labels,
+        transitions,
+        epsilons,
+        complete: explicit.complete,
+    }
+}
+
+/// Checks whether two minimized automata from the same `Aut` have the same transitions and epsilons
+pub fn same_structure(a: &ExplicitAut, b: &ExplicitAut) -> bool {
+    a.transitions == b.transitions && a.epsilons == b.epsilons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aut::Aut;
+    use crate::elim::eliminate;
+
+    #[test]
+    fn test_minimize_preserves_language() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "(x0:=1; dup)* + (x0:=1; dup)*; (x0:=1; dup)*",
+            "((dup + x1:=1) + x0:=1)* ; (dup + (x1:=1 + x0:=1))*",
+            "!(dup; dup) & X X (x1==1)",
+        ] {
+            let root = aut.parse_state(input);
+            let explicit = aut.explore(root);
+            let minimal = aut.minimize(root);
+            assert!(minimal.num_states() <= explicit.num_states(), "{}", input);
+            assert_eq!(minimal.states[0], root);
+            // The states are representatives from the explicit automaton,
+            // and the input-output relation is unchanged
+            assert!(minimal.states.iter().all(|state| explicit.states.contains(state)));
+            let store = aut.spp_store_mut();
+            assert_eq!(eliminate(&minimal, store), eliminate(&explicit, store), "{}", input);
+            // Minimizing is idempotent
+            let again = minimize(&minimal, aut.spp_store_mut());
+            assert!(same_structure(&minimal, &again), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_minimize_merges_states() {
+        let mut aut = Aut::new(2);
+        let root = aut.parse_state("(x0:=1; dup)* + (x0:=1; dup)*; (x0:=1; dup)*");
+        let explicit = aut.explore(root);
+        let minimal = aut.minimize(root);
+        assert!(minimal.num_states() < explicit.num_states());
+    }
+
+    #[test]
+    fn test_same_structure() {
+        let mut aut = Aut::new(2);
+        let a = aut.parse_state("((x0:=1; dup) + (x1:=1; dup) + dup)*");
+        let b = aut.parse_state("(dup + ((x1:=1; dup) + (x0:=1; dup)))*");
+        let c = aut.parse_state("(dup + (x1:=1; dup))*");
+        let (a, b, c) = (aut.minimize(a), aut.minimize(b), aut.minimize(c));
+        assert!(same_structure(&a, &b));
+        assert!(!same_structure(&a, &c));
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            // The final states differ only on packets with x0 = 0, which cannot reach them
+            ("x0:=1; dup; x0==1", "x0:=1; dup"),
+            ("x1:=0; dup; (x1==0; dup)*", "x1:=0; dup; dup*"),
+            ("(x0:=1; dup)* + (x0:=1; dup)*; (x0:=1; dup)*", "(x0:=1; dup)*"),
+            ("x0==1; dup; x0==0", "0"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert!(aut.equiv(s1, s2), "{} == {}", e1, e2);
+            let (a, b) = (aut.minimize(s1), aut.minimize(s2));
+            assert!(same_structure(&a, &b), "{} and {} minimize differently", e1, e2);
+        }
+    }
+}
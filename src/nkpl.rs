@@ -0,0 +1,502 @@
+// Front-end for the NKPL files of KATch1, such as its fuzz tests (fuzz100k.nkpl).
+//
+// NKPL fields are named with `@` and hold integers, e.g. `@sw=1 ⋅ @pt←2`. A file is a list of
+// let-bindings `name = e` and checks `check e1 ≡ e2` / `check e1 ≢ e2`. The operators are
+// (with their ASCII spellings), from the lowest to the highest precedence:
+// `∪ +`, `⊕ ^` and `-` (left associative), `∩ &`, `⋅ ;` (left associative), the prefix `¬ !`
+// and the postfix `⋆ *`. The constants are `∅ 0`, `ε 1` and `δ dup`, and the field operators
+// are the test `@x=v`, the negated test `@x≠v` (`@x!=v`) and the assignment `@x←v` (`@x:=v`).
+//
+// KATch1 fields range over all integers, so each field gets enough bits for the values that the
+// file mentions and two more. Tests and complements cannot tell the values that are never
+// mentioned apart, except by comparing two of them, so this is enough to decide the checks.
+
+use crate::expr::{Exp, Expr};
+use crate::fields::Fields;
+use crate::parser::{CheckOp, Cursor, ParseError, Program, Span, Stmt};
+use crate::pre::IntValue;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Field(String), // @name
+    Name(String),  // name of a binding
+    Num(IntValue), // 0 and 1 are also ∅ and ε
+    Check,         // check
+    Empty,         // ∅
+    Skip,          // ε
+    Dup,           // δ, dup
+    Test,          // = (also a binding)
+    NotTest,       // ≠, !=
+    Assign,        // ←, :=
+    Equiv,         // ≡, ==
+    NotEquiv,      // ≢
+    Union,         // ∪, +
+    Xor,           // ⊕, ^
+    Minus,         // -
+    Intersect,     // ∩, &
+    Seq,           // ⋅, ;
+    Not,           // ¬, !
+    Star,          // ⋆, *
+    LParen,        // (
+    RParen,        // )
+    Eof,           // End of input
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Token::Field(name) => return write!(f, "'@{}'", name),
+            Token::Name(name) => return write!(f, "'{}'", name),
+            Token::Num(n) => return write!(f, "'{}'", n),
+            Token::Check => "check",
+            Token::Empty => "∅",
+            Token::Skip => "ε",
+            Token::Dup => "δ",
+            Token::Test => "=",
+            Token::NotTest => "≠",
+            Token::Assign => "←",
+            Token::Equiv => "≡",
+            Token::NotEquiv => "≢",
+            Token::Union => "∪",
+            Token::Xor => "⊕",
+            Token::Minus => "-",
+            Token::Intersect => "∩",
+            Token::Seq => "⋅",
+            Token::Not => "¬",
+            Token::Star => "⋆",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Eof => return write!(f, "end of input"),
+        };
+        write!(f, "'{}'", text)
+    }
+}
+
+// The statements of KATch1 that have no counterpart here
+const UNSUPPORTED: &[&str] = &[
+    "print", "import", "for", "forward", "backward", "exists", "forall",
+];
+
+// --- Lexer ---
+
+// Recognizes the NKPL tokens; the cursor tracks spans and skips whitespace and comments
+struct Lexer<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer {
+            cursor: Cursor::new(input),
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.cursor.next_char()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.cursor.eat(c)
+    }
+
+    fn here(&self) -> Span {
+        self.cursor.here()
+    }
+
+    // Lexes the whole input; the last token is `Token::Eof`
+    fn tokenize(mut self) -> Result<Vec<(Token, Span)>, ParseError> {
+        let mut tokens = vec![];
+        loop {
+            self.cursor.skip_whitespace()?;
+            let start = self.here();
+            let Some(c) = self.next_char() else {
+                tokens.push((Token::Eof, start));
+                return Ok(tokens);
+            };
+            let token = match c {
+                '∅' => Token::Empty,
+                'ε' => Token::Skip,
+                'δ' => Token::Dup,
+                '≠' => Token::NotTest,
+                '←' => Token::Assign,
+                '≡' => Token::Equiv,
+                '≢' => Token::NotEquiv,
+                '∪' | '+' => Token::Union,
+                '⊕' | '^' => Token::Xor,
+                '-' => Token::Minus,
+                '∩' | '&' => Token::Intersect,
+                '⋅' | '·' | ';' => Token::Seq,
+                '¬' => Token::Not,
+                '⋆' | '*' => Token::Star,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '=' if self.eat('=') => Token::Equiv,
+                '=' => Token::Test,
+                '!' if self.eat('=') => Token::NotTest,
+                '!' => Token::Not,
+                ':' if self.eat('=') => Token::Assign,
+                '0'..='9' => {
+                    let mut digits = c.to_string();
+                    while let Some(&c) = self.cursor.peek_char().filter(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                        self.next_char();
+                    }
+                    match digits.parse() {
+                        Ok(n) => Token::Num(n),
+                        Err(_) => {
+                            let message = format!("Number too large: {}", digits);
+                            return Err(ParseError::new(message, start.to(self.here())));
+                        }
+                    }
+                }
+                '@' => match self.read_word() {
+                    Some(name) => Token::Field(name),
+                    None => {
+                        return Err(ParseError::new("Expected a field name after '@'", start.to(self.here())));
+                    }
+                },
+                c if c.is_alphabetic() || c == '_' => {
+                    let mut word = c.to_string();
+                    word.push_str(&self.read_word().unwrap_or_default());
+                    match word.as_str() {
+                        "check" => Token::Check,
+                        "dup" => Token::Dup,
+                        _ => Token::Name(word),
+                    }
+                }
+                c => {
+                    let message = format!("Unexpected character: {}", c);
+                    return Err(ParseError::new(message, start.to(self.here())));
+                }
+            };
+            tokens.push((token, start.to(self.here())));
+        }
+    }
+
+    // Reads the rest of a name
+    fn read_word(&mut self) -> Option<String> {
+        let mut word = String::new();
+        while let Some(&c) = self.cursor.peek_char().filter(|c| c.is_alphanumeric() || **c == '_') {
+            word.push(c);
+            self.next_char();
+        }
+        (!word.is_empty()).then_some(word)
+    }
+}
+
+// --- Parser ---
+
+struct Parser {
+    tokens: Vec<(Token, Span)>,
+    next: usize, // The index of the next token
+    fields: Fields,
+    bindings: HashMap<String, Exp>,
+}
+
+impl Parser {
+    // The number of bits of a field whose largest value is `max`: every value up to `max`
+    // and two values that the file does not mention
+    fn field_width(max: IntValue) -> u32 {
+        (IntValue::BITS - max.saturating_add(2).leading_zeros()).max(1)
+    }
+
+    // Declares the fields in the order of their first use, with widths for their largest values
+    fn declare_fields(tokens: &[(Token, Span)]) -> Result<Fields, ParseError> {
+        let mut order: Vec<(&str, Span)> = vec![];
+        let mut max: HashMap<&str, IntValue> = HashMap::new();
+        for (i, (token, span)) in tokens.iter().enumerate() {
+            if let Token::Field(name) = token {
+                if !max.contains_key(name.as_str()) {
+                    order.push((name, *span));
+                }
+                let value = match tokens.get(i + 2) {
+                    Some((Token::Num(n), _)) => *n,
+                    _ => 0,
+                };
+                let entry = max.entry(name).or_insert(0);
+                *entry = (*entry).max(value);
+            }
+        }
+        let mut fields = Fields::new();
+        for (name, span) in order {
+            fields
+                .declare(name, Self::field_width(max[name]))
+                .map_err(|message| ParseError::new(message, span))?;
+        }
+        Ok(fields)
+    }
+
+    fn next_token(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::Eof {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn peek_token(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    // The span of the last token read
+    fn span(&self) -> Span {
+        self.tokens[self.next.saturating_sub(1)].1
+    }
+
+    // An error at the last token read
+    fn error(&self, message: String) -> ParseError {
+        ParseError::new(message, self.span())
+    }
+
+    fn parse_program(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut stmts = vec![];
+        loop {
+            match self.next_token() {
+                Token::Eof => return Ok(stmts),
+                Token::Check => {
+                    let left = self.parse_union()?;
+                    let op = match self.next_token() {
+                        Token::Equiv => CheckOp::Equiv,
+                        Token::NotEquiv | Token::NotTest => CheckOp::NotEquiv,
+                        other => {
+                            let error = self.error(format!("Expected '≡' or '≢' in check statement, found {}", other));
+                            return Err(match other {
+                                Token::Test => error.with_help("use `≡` (or `==`) to check equivalence"),
+                                _ => error,
+                            });
+                        }
+                    };
+                    let right = self.parse_union()?;
+                    stmts.push(Stmt::Check(op, left, right));
+                }
+                Token::Name(name) if *self.peek_token() == Token::Test => {
+                    self.next_token(); // Consume '='
+                    let expr = self.parse_union()?;
+                    self.bindings.insert(name, expr);
+                }
+                Token::Name(name) if UNSUPPORTED.contains(&name.as_str()) => {
+                    return Err(self.error(format!("`{}` statements are not supported", name)));
+                }
+                other => {
+                    return Err(self.error(format!(
+                        "Expected `check` or a binding `name = e`, found {}",
+                        other
+                    )));
+                }
+            }
+        }
+    }
+
+    // Handles '∪', '⊕' and '-'
+    fn parse_union(&mut self) -> Result<Exp, ParseError> {
+        let mut left = self.parse_intersect()?;
+        loop {
+            let op: fn(Exp, Exp) -> Exp = match self.peek_token() {
+                Token::Union => Expr::union,
+                Token::Xor => Expr::xor,
+                Token::Minus => Expr::difference,
+                _ => return Ok(left),
+            };
+            self.next_token();
+            let right = self.parse_intersect()?;
+            left = op(left, right);
+        }
+    }
+
+    // Handles '∩'
+    fn parse_intersect(&mut self) -> Result<Exp, ParseError> {
+        let mut left = self.parse_sequence()?;
+        while let Token::Intersect = self.peek_token() {
+            self.next_token();
+            let right = self.parse_sequence()?;
+            left = Expr::intersect(left, right);
+        }
+        Ok(left)
+    }
+
+    // Handles '⋅'
+    fn parse_sequence(&mut self) -> Result<Exp, ParseError> {
+        let mut left = self.parse_unary()?;
+        while let Token::Seq = self.peek_token() {
+            self.next_token();
+            let right = self.parse_unary()?;
+            left = Expr::sequence(left, right);
+        }
+        Ok(left)
+    }
+
+    // Handles the prefix '¬' and the postfix '⋆'
+    fn parse_unary(&mut self) -> Result<Exp, ParseError> {
+        if let Token::Not = self.peek_token() {
+            self.next_token();
+            return Ok(Expr::complement(self.parse_unary()?));
+        }
+        let mut expr = self.parse_primary()?;
+        while let Token::Star = self.peek_token() {
+            self.next_token();
+            expr = Expr::star(expr);
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Exp, ParseError> {
+        match self.next_token() {
+            Token::Empty | Token::Num(0) => Ok(Expr::zero()),
+            Token::Skip | Token::Num(1) => Ok(Expr::one()),
+            Token::Dup => Ok(Expr::dup()),
+            Token::Field(name) => self.parse_field_op(&name),
+            Token::Name(name) => match self.bindings.get(&name) {
+                Some(expr) => Ok(expr.clone()),
+                None => Err(self.error(format!("Undefined name `{}`", name))),
+            },
+            Token::LParen => {
+                let open = self.span();
+                let expr = self.parse_union()?;
+                match self.next_token() {
+                    Token::RParen => Ok(expr),
+                    other => Err(self
+                        .error(format!("Expected closing parenthesis ')', found {}", other))
+                        .with_help(format!(
+                            "the '(' at line {}, column {} is not closed",
+                            open.line, open.column
+                        ))),
+                }
+            }
+            other => Err(self.error(format!("Expected an expression, found {}", other))),
+        }
+    }
+
+    // Parses the `=v`, `≠v` or `←v` after the field `@name`
+    fn parse_field_op(&mut self, name: &str) -> Result<Exp, ParseError> {
+        let decl = self.fields.get(name).unwrap();
+        let (start, width) = (decl.start, decl.width);
+        let op = self.next_token();
+        let value = match self.next_token() {
+            Token::Num(n) => n,
+            other => {
+                return Err(self.error(format!("Expected a value after {}, found {}", op, other)));
+            }
+        };
+        match op {
+            Token::Test => Ok(Expr::test_int(start, width, value)),
+            // A negated test keeps the packets that fail the test
+            Token::NotTest => Ok(Expr::difference(Expr::one(), Expr::test_int(start, width, value))),
+            Token::Assign => Ok(Expr::assign_int(start, width, value)),
+            other => {
+                let message = format!("Expected '=', '≠' or '←' after field `@{}`, found {}", name, other);
+                Err(ParseError::new(message, self.tokens[self.next - 2].1))
+            }
+        }
+    }
+}
+
+/// Parses an NKPL file into a program whose statements are its checks
+pub fn parse_nkpl(input: &str) -> Result<Program, ParseError> {
+    let tokens = Lexer::new(input).tokenize()?;
+    let fields = Parser::declare_fields(&tokens)?;
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        fields,
+        bindings: HashMap::new(),
+    };
+    let stmts = parser.parse_program()?;
+    Ok(Program {
+        fields: parser.fields,
+        stmts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aut::Aut;
+
+    // Decides the checks of an NKPL file, returning whether each one passed
+    fn run(input: &str) -> Vec<bool> {
+        let program = parse_nkpl(input).unwrap();
+        let mut aut = Aut::new(program.fields.num_bits());
+        let mut passed = vec![];
+        for stmt in &program.stmts {
+            let Stmt::Check(op, e1, e2) = stmt else { unreachable!() };
+            let s1 = aut.expr_to_state(e1);
+            let s2 = aut.expr_to_state(e2);
+            let equiv = aut.equiv_counterexample(s1, s2).is_none();
+            passed.push(equiv == (*op == CheckOp::Equiv));
+        }
+        passed
+    }
+
+    #[test]
+    fn test_field_width() {
+        for (max, width) in [(0, 2), (1, 2), (2, 3), (5, 3), (6, 4), (IntValue::MAX, 64)] {
+            assert_eq!(Parser::field_width(max), width, "{}", max);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let program = parse_nkpl("net = @sw=1 ⋅ @pt←5\ncheck (net ∪ δ)⋆ ≡ ∅\n// ASCII\ncheck net; dup + 1 != 0").unwrap();
+        let sw = program.fields.get("sw").unwrap().clone();
+        let pt = program.fields.get("pt").unwrap().clone();
+        assert_eq!((sw.start, sw.width), (0, 2));
+        assert_eq!((pt.start, pt.width), (2, 3));
+        let net = Expr::sequence(Expr::test_int(0, 2, 1), Expr::assign_int(2, 3, 5));
+        assert_eq!(
+            program.stmts,
+            vec![
+                Stmt::Check(
+                    CheckOp::Equiv,
+                    Expr::star(Expr::union(net.clone(), Expr::dup())),
+                    Expr::zero()
+                ),
+                Stmt::Check(
+                    CheckOp::NotEquiv,
+                    Expr::union(Expr::sequence(net, Expr::dup()), Expr::one()),
+                    Expr::zero()
+                ),
+            ]
+        );
+        // ∩ binds weaker than ⋅, and ¬ and ⋆ bind stronger
+        assert_eq!(
+            parse_nkpl("check ¬δ⋆ ∩ ε⋅δ ≢ @a≠0").unwrap().stmts,
+            vec![Stmt::Check(
+                CheckOp::NotEquiv,
+                Expr::intersect(
+                    Expr::complement(Expr::star(Expr::dup())),
+                    Expr::sequence(Expr::one(), Expr::dup())
+                ),
+                Expr::difference(Expr::one(), Expr::test_int(0, 2, 0))
+            )]
+        );
+    }
+
+    #[test]
+    fn test_checks() {
+        let passed = run("
+            check @a←1 ⋅ @a=1 ≡ @a←1
+            check @a=1 ⋅ @a=2 ≡ ∅
+            check @a=1 ∪ @a≠1 ≡ ε
+            check @a≠1 ⋅ @a≠2 ≢ ∅
+            check (@a←1 ⋅ δ)⋆ ≡ ε ∪ (@a←1 ⋅ δ)⋆ ⋅ @a←1 ⋅ δ
+            check @a←2 ⋅ δ ≡ @a←1 ⋅ δ
+        ");
+        assert_eq!(passed, vec![true, true, true, true, true, false]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = parse_nkpl("check @a=1 ≡\n  @a = ε").unwrap_err();
+        assert_eq!(error.message, "Expected a value after '=', found 'ε'");
+        assert_eq!(error.span, Span { start: 22, end: 24, line: 2, column: 8 });
+        let error = parse_nkpl("check @a=1 = @a=1").unwrap_err();
+        assert_eq!(error.help.as_deref(), Some("use `≡` (or `==`) to check equivalence"));
+        assert_eq!(parse_nkpl("check x ≡ ∅").unwrap_err().message, "Undefined name `x`");
+        assert_eq!(parse_nkpl("print ∅").unwrap_err().message, "`print` statements are not supported");
+        assert!(parse_nkpl("check @a≡1 ≡ ∅").is_err());
+        assert!(parse_nkpl("check (δ ≡ ∅").is_err());
+        assert!(parse_nkpl("check 2 ≡ ∅").is_err());
+        assert!(parse_nkpl("check δ ≡ ∅ $").is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// Omega mode: expressions interpreted over infinite strings pk0 pk1 pk2 ..., where every pair
+// (pk_i, pk_{i+1}) is taken by a transition and there is no output packet (no epsilon).
+//
+// The omega automaton is built from the derivatives (see `Aut::explore_omega`). The expression
+// is first put in negation normal form, with `!(e1 U e2) = !e1 R !e2` and `!X e = X !e`.
+// Unions are split into nondeterministic choices, so a state is a conjunction of atoms:
+// LTL operators, and NetKAT expressions whose derivatives are computed as usual.
+// An atom that is an until, or a NetKAT expression without a complement at the top, is an
+// obligation: a run may not stay in it forever. In particular a NetKAT expression without LTL
+// operators has no infinite strings of its own; `e; T` and `G (e; T)` do.
+//
+// Since the atoms can change into other atoms as the run proceeds, we track the obligations
+// with the breakpoint construction of Miyano and Hayashi: a state also records the obligations
+// that are still waiting to be discharged, and is accepting when there are none. The omega
+// language of the automaton consists of the strings with a run that is accepting infinitely
+// often (Büchi acceptance).
+//
+// Emptiness is decided symbolically, on sets of (state, packet) pairs represented by one SP
+// per state, with the fixpoint of Emerson and Lei: the fair pairs are the greatest set Z such
+// that every pair in Z has a successor in Z from which an accepting pair in Z can be reached
+// within Z. Counterexamples are lassos: a path to a fair pair, followed by a cycle through
+// an accepting pair back to itself.
+
+use crate::aut::State;
+use crate::sp::SP;
+use crate::spp::{SPP, SPPstore};
+use crate::trace::{Lasso, Packet};
+
+/// The omega automaton of an expression, see the module documentation
+#[derive(Debug, Clone)]
+pub struct OmegaAut {
+    /// The atoms of each state, and the obligations among them that are still waiting
+    pub states: Vec<(Vec<State>, Vec<State>)>,
+    /// The initial states, one for each disjunct of the expression
+    pub initial: Vec<usize>,
+    pub transitions: Vec<Vec<(usize, SPP)>>,
+    /// A state is accepting iff no obligations are waiting
+    pub accepting: Vec<bool>,
+}
+
+/// A node of the product of the automaton with the packets
+type Node = (usize, Packet);
+
+impl OmegaAut {
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns, for each state, the packets from which some string is accepted
+    pub fn fair_states(&self, store: &mut SPPstore) -> Vec<SP> {
+        let n = self.num_states();
+        let mut fair = vec![store.sp.one; n];
+        loop {
+            let accepting = self.accepting_part(store, &fair);
+            let reach = self.until(store, &fair, &accepting);
+            let next = self.pre(store, &reach);
+            let next: Vec<SP> = (0..n).map(|s| store.sp.intersect(next[s], fair[s])).collect();
+            if next == fair {
+                return fair;
+            }
+            fair = next;
+        }
+    }
+
+    /// Checks whether the omega language is empty
+    pub fn is_empty(&self, store: &mut SPPstore) -> bool {
+        let fair = self.fair_states(store);
+        self.initial.iter().all(|&s| fair[s] == store.sp.zero)
+    }
+
+    /// Returns an accepted infinite string, or `None` if the omega language is empty
+    pub fn lasso(&self, store: &mut SPPstore) -> Option<Lasso> {
+        let fair = self.fair_states(store);
+        let accepting = self.accepting_part(store, &fair);
+        let start = self
+            .initial
+            .iter()
+            .find_map(|&s| store.sp.pick(fair[s]).map(|packet| (s, packet)))?;
+        let mut path: Vec<Node> = vec![start];
+        // The positions on the path of the accepting nodes visited so far
+        let mut visited: Vec<usize> = vec![];
+        loop {
+            let current = path.last().unwrap().clone();
+            // Close the cycle if one of the visited accepting nodes can be reached again
+            let mut targets = vec![store.sp.zero; self.num_states()];
+            for &i in &visited {
+                let (state, packet) = &path[i];
+                let node = store.sp.singleton(packet);
+                targets[*state] = store.sp.union(targets[*state], node);
+            }
+            if !visited.is_empty()
+                && let Some(steps) = self.path(store, &current, &fair, &targets)
+            {
+                let end = steps.last().unwrap();
+                let start = visited.into_iter().find(|&i| path[i] == *end).unwrap();
+                path.extend(steps);
+                path.pop();
+                let mut packets: Vec<Packet> = path.into_iter().map(|(_, packet)| packet).collect();
+                let cycle = packets.split_off(start);
+                return Some(Lasso::new(packets, cycle));
+            }
+            // Otherwise move on to an accepting node that was not visited yet
+            let steps = self
+                .path(store, &current, &fair, &accepting)
+                .expect("a fair node reaches an accepting node");
+            path.extend(steps);
+            visited.push(path.len() - 1);
+        }
+    }
+
+    /// Checks whether the infinite string of the lasso is accepted
+    pub fn accepts(&self, store: &SPPstore, lasso: &Lasso) -> bool {
+        // The product of the automaton with the positions of the lasso is finite
+        let len = lasso.prefix.len() + lasso.cycle.len();
+        let next_pos = |pos: usize| if pos + 1 < len { pos + 1 } else { lasso.prefix.len() };
+        let successors = |(state, pos): (usize, usize)| -> Vec<(usize, usize)> {
+            self.transitions[state]
+                .iter()
+                .filter(|&&(_, spp)| store.contains(spp, lasso.packet(pos), lasso.packet(pos + 1)))
+                .map(|&(target, _)| (target, next_pos(pos)))
+                .collect()
+        };
+        let reachable_from = |from: Vec<(usize, usize)>| -> Vec<(usize, usize)> {
+            let mut seen = from.clone();
+            let mut todo = from;
+            while let Some(node) = todo.pop() {
+                for next in successors(node) {
+                    if !seen.contains(&next) {
+                        seen.push(next);
+                        todo.push(next);
+                    }
+                }
+            }
+            seen
+        };
+        let initial = self.initial.iter().map(|&s| (s, 0)).collect();
+        reachable_from(initial).into_iter().any(|node| {
+            self.accepting[node.0] && reachable_from(successors(node)).contains(&node)
+        })
+    }
+
+    // The part of `set` in the accepting states
+    fn accepting_part(&self, store: &mut SPPstore, set: &[SP]) -> Vec<SP> {
+        (0..self.num_states())
+            .map(|s| if self.accepting[s] { set[s] } else { store.sp.zero })
+            .collect()
+    }
+
+    // The nodes with a transition into `set`
+    fn pre(&self, store: &mut SPPstore, set: &[SP]) -> Vec<SP> {
+        let mut result = vec![store.sp.zero; self.num_states()];
+        for (state, transitions) in self.transitions.iter().enumerate() {
+            for &(target, spp) in transitions {
+                let sources = store.pull(spp, set[target]);
+                result[state] = store.sp.union(result[state], sources);
+            }
+        }
+        result
+    }
+
+    // The nodes that reach `target` by a path that stays within `within` before it
+    fn until(&self, store: &mut SPPstore, within: &[SP], target: &[SP]) -> Vec<SP> {
+        let mut reach = target.to_vec();
+        loop {
+            let pre = self.pre(store, &reach);
+            let mut changed = false;
+            for s in 0..self.num_states() {
+                let new = store.sp.intersect(pre[s], within[s]);
+                let new = store.sp.union(reach[s], new);
+                changed |= new != reach[s];
+                reach[s] = new;
+            }
+            if !changed {
+                return reach;
+            }
+        }
+    }
+
+    /// A shortest path of at least one step from `from` to a node in `target`,
+    /// staying within `within`. The path does not include `from`.
+    fn path(
+        &self,
+        store: &mut SPPstore,
+        from: &Node,
+        within: &[SP],
+        target: &[SP],
+    ) -> Option<Vec<Node>> {
+        let n = self.num_states();
+        let mut layer = vec![store.sp.zero; n];
+        layer[from.0] = store.sp.singleton(&from.1);
+        let mut seen = layer.clone();
+        let mut layers = vec![layer];
+        let end = loop {
+            let mut next = vec![store.sp.zero; n];
+            for (state, transitions) in self.transitions.iter().enumerate() {
+                for &(t, spp) in transitions {
+                    let image = store.push(layers.last().unwrap()[state], spp);
+                    let image = store.sp.intersect(image, within[t]);
+                    next[t] = store.sp.union(next[t], image);
+                }
+            }
+            let hit = (0..n).find_map(|t| {
+                let hit = store.sp.intersect(next[t], target[t]);
+                store.sp.pick(hit).map(|packet| (t, packet))
+            });
+            if let Some(end) = hit {
+                break end;
+            }
+            let mut grew = false;
+            for t in 0..n {
+                next[t] = store.sp.difference(next[t], seen[t]);
+                seen[t] = store.sp.union(seen[t], next[t]);
+                grew |= next[t] != store.sp.zero;
+            }
+            if !grew {
+                return None;
+            }
+            layers.push(next);
+        };
+
+        // Walk back through the layers, picking a predecessor for each node
+        let mut path = vec![end];
+        for layer in layers.iter().skip(1).rev() {
+            let (t, packet) = path.last().unwrap().clone();
+            let node = store.sp.singleton(&packet);
+            let prev = (0..n).find_map(|s| {
+                let spp = self.transitions[s].iter().find(|&&(t2, _)| t2 == t)?.1;
+                let sources = store.pull(spp, node);
+                let sources = store.sp.intersect(sources, layer[s]);
+                store.sp.pick(sources).map(|packet| (s, packet))
+            });
+            path.push(prev.unwrap());
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aut::Aut;
+
+    #[test]
+    fn test_omega_empty() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "0",
+            "x0==1",
+            "(x0:=1; dup)*",
+            "x0==1; dup; x0==0",
+            "G (x0==1; T) & F (x0==0; T)",
+            "G F (x0==1; T) & F G (x0==0; T)",
+            "G (x0==1; T) & X (x0==0; T)",
+            "((x0==0; T) U (x1==0; T)) - F (x1==0; T)",
+        ] {
+            let state = aut.parse_state(input);
+            assert!(aut.omega_is_empty(state), "{} should be empty", input);
+            assert_eq!(aut.omega_witness(state), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_omega_witness_is_accepted() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "T",
+            "x0==1; T",
+            "!(x0:=1; dup)*",
+            "G (x0==1; T)",
+            "G F (x0==1; T) & G F (x0==0; T)",
+            "F G (x1==1; T) & G ((x0==0; T) + X (x0==0; T))",
+            "(x0==0; T) U (x1==1; T)",
+            "G ((x0==1; T) ^ X (x0==1; T))",
+            "!G F (x0==1; T)",
+        ] {
+            let state = aut.parse_state(input);
+            let lasso = aut.omega_witness(state).expect(input);
+            assert!(aut.omega_accepts(state, &lasso), "{} does not accept {}", input, lasso);
+            // The complement does not accept it
+            let complement = aut.parse_state(&format!("!({})", input));
+            assert!(!aut.omega_accepts(complement, &lasso), "!({}) accepts {}", input, lasso);
+        }
+    }
+
+    #[test]
+    fn test_omega_lasso() {
+        // The packets alternate between x0=0 and x0=1
+        let mut aut = Aut::new(1);
+        let state = aut.parse_state("G (((x0==0; T) & X (x0==1; T)) + ((x0==1; T) & X (x0==0; T)))");
+        let lasso = aut.omega_witness(state).unwrap();
+        assert_eq!(lasso.cycle.len() % 2, 0);
+        assert_ne!(lasso.cycle[0], lasso.cycle[1]);
+        assert_eq!(lasso.to_string(), format!("{}", lasso.display(aut.fields())));
+        assert!(lasso.to_string().ends_with(")^ω"));
+    }
+
+    #[test]
+    fn test_omega_equiv() {
+        let mut aut = Aut::new(2);
+        for (e1, e2) in [
+            ("G G (x0==1; T)", "G (x0==1; T)"),
+            ("F F (x0==1; T)", "F (x0==1; T)"),
+            ("G F G F (x0==1; T)", "G F (x0==1; T)"),
+            ("!X (x0==1; T)", "X !(x0==1; T)"),
+            ("!((x0==1; T) U (x1==1; T))", "!(x0==1; T) R !(x1==1; T)"),
+            ("F (x0==1; T) + F (x1==1; T)", "F ((x0==1; T) + (x1==1; T))"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            assert_eq!(aut.omega_equiv_counterexample(s1, s2), None, "{} == {}", e1, e2);
+        }
+        for (e1, e2) in [
+            ("G F (x0==1; T)", "F G (x0==1; T)"),
+            ("X (x0==1; T)", "(x0==1; T)"),
+            ("F (x0==1; T) & F (x1==1; T)", "F ((x0==1; T) & (x1==1; T))"),
+        ] {
+            let (s1, s2) = (aut.parse_state(e1), aut.parse_state(e2));
+            let lasso = aut.omega_equiv_counterexample(s1, s2).expect(e1);
+            assert_ne!(
+                aut.omega_accepts(s1, &lasso),
+                aut.omega_accepts(s2, &lasso),
+                "{} does not distinguish {} and {}",
+                lasso,
+                e1,
+                e2
+            );
+        }
+        // On infinite strings, F G implies G F
+        let (s1, s2) = (aut.parse_state("F G (x0==1; T)"), aut.parse_state("G F (x0==1; T)"));
+        assert_eq!(aut.omega_includes_counterexample(s1, s2), None);
+        assert!(aut.omega_includes_counterexample(s2, s1).is_some());
+    }
+}
@@ -1,4 +1,7 @@
 use crate::expr::{Exp, Expr};
+use crate::fields::Fields;
+use crate::pre::{Field, IntValue};
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::str::Chars;
 
@@ -24,50 +27,265 @@ pub enum Token {
     LtlF,       // F
     LtlG,       // G
     LtlR,       // R
+    LtlY,       // Y
+    LtlZ,       // Z
+    LtlS,       // S
+    LtlO,       // O
+    LtlH,       // H
+    LAngle,     // < (LDL diamond)
+    RAngle,     // >
+    LBracket,   // [ (LDL box)
+    RBracket,   // ]
     LParen,     // (
     RParen,     // )
     Field(u32), // x followed by digits
+    FieldRange(u32, u32), // x[lo..hi], the bits lo..hi-1 of a multi-bit field
+    Num(IntValue), // numbers other than 0 and 1
     End,        // end
+    Check,      // check
+    Leq,        // <=
+    Neq,        // !=
+    FieldKw,    // field
+    Colon,      // :
+    Ident(String), // name of a declared field
+    Proj,       // proj
+    Rev,        // rev
+    LBrace,     // {
+    RBrace,     // }
+    Comma,      // ,
+    Let,        // let
+    Define,     // = (in a definition)
+    Spliced(usize), // An argument of a definition, substituted while expanding it (never lexed)
     Eof,        // End of input
 }
 
-pub struct Lexer<'a> {
+// Tokens are shown as they are written in error messages
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Token::Zero => "0",
+            Token::One => "1",
+            Token::Top => "T",
+            Token::Assign => ":=",
+            Token::Eq => "==",
+            Token::Plus => "+",
+            Token::And => "&",
+            Token::Xor => "^",
+            Token::Minus => "-",
+            Token::Not => "!",
+            Token::Semicolon => ";",
+            Token::Star => "*",
+            Token::Dup => "dup",
+            Token::LtlX => "X",
+            Token::LtlU => "U",
+            Token::LtlF => "F",
+            Token::LtlG => "G",
+            Token::LtlR => "R",
+            Token::LtlY => "Y",
+            Token::LtlZ => "Z",
+            Token::LtlS => "S",
+            Token::LtlO => "O",
+            Token::LtlH => "H",
+            Token::LAngle => "<",
+            Token::RAngle => ">",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Field(idx) => return write!(f, "'x{}'", idx),
+            Token::FieldRange(lo, hi) => return write!(f, "'x[{}..{}]'", lo, hi),
+            Token::Num(n) => return write!(f, "'{}'", n),
+            Token::End => "end",
+            Token::Check => "check",
+            Token::Leq => "<=",
+            Token::Neq => "!=",
+            Token::FieldKw => "field",
+            Token::Colon => ":",
+            Token::Ident(name) => return write!(f, "'{}'", name),
+            Token::Proj => "proj",
+            Token::Rev => "rev",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::Comma => ",",
+            Token::Let => "let",
+            Token::Define => "=",
+            Token::Spliced(_) => return write!(f, "an argument"),
+            Token::Eof => return write!(f, "end of input"),
+        };
+        write!(f, "'{}'", text)
+    }
+}
+
+/// A range of bytes of the source, with the line and the column (in characters, both
+/// counted from 1) where it starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: self.end.max(other.end),
+            ..self
+        }
+    }
+}
+
+/// A token with the span of the source it was lexed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// An error of the lexer or the parser, pointing at a span of the source,
+/// with a suggestion for common mistakes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+    pub help: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        ParseError {
+            message: message.into(),
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the error with the line of `source` it points at, for example
+    /// ```text
+    /// error: Expected '==' or ':=' after field `sw`, found '='
+    ///  --> net.k2:3:4
+    ///   |
+    /// 3 | sw = 1
+    ///   |    ^
+    ///   = help: use `==` to test a field or `:=` to assign it
+    /// ```
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        // Keep the tabs before the caret so that it lines up with the source
+        let indent: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = self.span.end.clamp(start, line_end);
+        let carets = "^".repeat(source[start..end].chars().count().max(1));
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let mut out = format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.message,
+            gutter,
+            path,
+            self.span.line,
+            self.span.column,
+            gutter,
+            number,
+            source[line_start..line_end].trim_end_matches('\r'),
+            gutter,
+            indent,
+            carets
+        );
+        if let Some(help) = &self.help {
+            out.push_str(&format!("\n{} = help: {}", gutter, help));
+        }
+        out
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.message)
+    }
+}
+
+/// A cursor over the characters of an input that tracks the span of the next character.
+/// Both the `.k2` lexer and the NKPL lexer read their input through it.
+pub(crate) struct Cursor<'a> {
     iter: Peekable<Chars<'a>>,
+    pos: usize,    // The byte offset of the next character
+    line: usize,   // The line of the next character
+    column: usize, // The column of the next character
 }
 
-impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
-        Lexer {
+impl<'a> Cursor<'a> {
+    pub(crate) fn new(input: &'a str) -> Self {
+        Cursor {
             iter: input.chars().peekable(),
+            pos: 0,
+            line: 1,
+            column: 1,
         }
     }
 
-    fn next_char(&mut self) -> Option<char> {
-        self.iter.next()
+    pub(crate) fn next_char(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
     }
 
-    fn peek_char(&mut self) -> Option<&char> {
+    pub(crate) fn peek_char(&mut self) -> Option<&char> {
         self.iter.peek()
     }
 
-    fn skip_whitespace(&mut self) -> Result<(), String> {
+    /// Consumes the next character if it is `c`
+    pub(crate) fn eat(&mut self, c: char) -> bool {
+        if self.peek_char() == Some(&c) {
+            self.next_char();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The empty span at the next character
+    pub(crate) fn here(&self) -> Span {
+        Span {
+            start: self.pos,
+            end: self.pos,
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Skips whitespace (including newlines) and `//` comments
+    pub(crate) fn skip_whitespace(&mut self) -> Result<(), ParseError> {
         while let Some(&c) = self.peek_char() {
             if c == '/' {
+                let start = self.here();
                 // Skip the first '/'
                 self.next_char();
-                if self.peek_char() == Some(&'/') {
-                    // Skip the second '/'
-                    self.next_char();
+                if self.eat('/') {
                     // Consume rest of line except newline
-                    while let Some(&c) = self.peek_char() {
-                        if c == '\n' {
-                            break;
-                        }
+                    while self.peek_char().is_some_and(|&c| c != '\n') {
                         self.next_char();
                     }
                     continue;
                 } else {
-                    return Err("Unexpected character after '/'".to_string());
+                    return Err(ParseError::new("Unexpected character after '/'", start.to(self.here()))
+                        .with_help("comments start with `//`"));
                 }
             }
             if !c.is_whitespace() {
@@ -77,48 +295,117 @@ impl<'a> Lexer<'a> {
         }
         Ok(())
     }
+}
+
+pub struct Lexer<'a> {
+    cursor: Cursor<'a>,
+    after: Span, // The empty span after the last token, where the end of input is reported
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer {
+            cursor: Cursor::new(input),
+            after: Span {
+                start: 0,
+                end: 0,
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.cursor.next_char()
+    }
+
+    fn peek_char(&mut self) -> Option<&char> {
+        self.cursor.peek_char()
+    }
+
+    fn here(&self) -> Span {
+        self.cursor.here()
+    }
+
+    /// Lexes the next token, or `Token::Eof` at the end of the input
+    pub fn next_token(&mut self) -> Result<SpannedToken, ParseError> {
+        self.cursor.skip_whitespace()?; // Skips whitespace AND comments
+        let start = self.here();
+        let token = self
+            .read_token()
+            .map_err(|message| ParseError::new(message, start.to(self.here())))?;
+        if token == Token::Eof {
+            return Ok(SpannedToken {
+                token,
+                span: self.after,
+            });
+        }
+        self.after = self.here();
+        Ok(SpannedToken {
+            token,
+            span: start.to(self.here()),
+        })
+    }
 
-    pub fn next_token(&mut self) -> Result<Token, String> {
-        self.skip_whitespace()?; // Skips whitespace AND comments, but not newlines
+    fn read_token(&mut self) -> Result<Token, String> {
         match self.next_char() {
             None => Ok(Token::Eof),
             Some(c) => match c {
-                '0' => Ok(Token::Zero),
-                '1' => Ok(Token::One),
+                '0'..='9' => {
+                    let num = self.read_number(c)?;
+                    match num {
+                        0 => Ok(Token::Zero),
+                        1 => Ok(Token::One),
+                        _ => Ok(Token::Num(num)),
+                    }
+                }
                 '+' => Ok(Token::Plus),
                 '&' => Ok(Token::And),
                 '^' => Ok(Token::Xor),
                 '-' => Ok(Token::Minus),
-                '!' => Ok(Token::Not),
+                '!' => {
+                    if self.peek_char() == Some(&'=') {
+                        self.next_char();
+                        Ok(Token::Neq)
+                    } else {
+                        Ok(Token::Not)
+                    }
+                }
+                '<' => {
+                    if self.peek_char() == Some(&'=') {
+                        self.next_char();
+                        Ok(Token::Leq)
+                    } else {
+                        Ok(Token::LAngle)
+                    }
+                }
+                '>' => Ok(Token::RAngle),
+                '[' => Ok(Token::LBracket),
+                ']' => Ok(Token::RBracket),
                 ';' => Ok(Token::Semicolon),
                 '*' => Ok(Token::Star),
                 '(' => Ok(Token::LParen),
                 ')' => Ok(Token::RParen),
+                '{' => Ok(Token::LBrace),
+                '}' => Ok(Token::RBrace),
+                ',' => Ok(Token::Comma),
                 'T' => Ok(Token::Top),
                 'U' => Ok(Token::LtlU),
                 'X' => Ok(Token::LtlX),
                 'F' => Ok(Token::LtlF),
                 'G' => Ok(Token::LtlG),
                 'R' => Ok(Token::LtlR),
-                'e' => {
-                    if self.peek_char() == Some(&'n') {
-                        self.next_char();
-                        if self.peek_char() == Some(&'d') {
-                            self.next_char();
-                            Ok(Token::End)
-                        } else {
-                            Err("Expected 'end'".to_string())
-                        }
-                    } else {
-                        Err("Expected 'end'".to_string())
-                    }
-                }
+                'Y' => Ok(Token::LtlY),
+                'Z' => Ok(Token::LtlZ),
+                'S' => Ok(Token::LtlS),
+                'O' => Ok(Token::LtlO),
+                'H' => Ok(Token::LtlH),
                 ':' => {
                     if self.peek_char() == Some(&'=') {
                         self.next_char();
                         Ok(Token::Assign)
                     } else {
-                        Err("Expected ':' after ':' for assignment".to_string())
+                        Ok(Token::Colon)
                     }
                 }
                 '=' => {
@@ -126,103 +413,461 @@ impl<'a> Lexer<'a> {
                         self.next_char();
                         Ok(Token::Eq)
                     } else {
-                        Err("Expected '=' after '=' for equality test".to_string())
+                        Ok(Token::Define)
                     }
                 }
-                'x' => {
-                    let mut num_str = String::new();
-                    while let Some(&c) = self.peek_char() {
-                        if c.is_digit(10) {
-                            num_str.push(self.next_char().unwrap());
-                        } else {
-                            break;
-                        }
+                'x' if self.peek_char() == Some(&'[') => {
+                    // x[lo..hi]
+                    self.next_char();
+                    let lo = self.read_field_index()?;
+                    if self.next_char() != Some('.') || self.next_char() != Some('.') {
+                        return Err("Expected '..' in field range".to_string());
                     }
-                    if num_str.is_empty() {
-                        Err("Expected digits after 'x' for field".to_string())
-                    } else {
-                        match num_str.parse::<u32>() {
-                            Ok(index) => Ok(Token::Field(index)),
-                            Err(_) => Err("Invalid field index number".to_string()),
-                        }
+                    let hi = self.read_field_index()?;
+                    if self.next_char() != Some(']') {
+                        return Err("Expected ']' after field range".to_string());
                     }
-                }
-                'd' => {
-                    if self.peek_char() == Some(&'u') {
-                        self.next_char();
-                        if self.peek_char() == Some(&'p') {
-                            self.next_char();
-                            Ok(Token::Dup)
-                        } else {
-                            Err("Expected `dup`".to_string())
-                        }
-                    } else {
-                        Err("Expected `dup`".to_string())
+                    if hi <= lo {
+                        return Err(format!("Empty field range x[{}..{}]", lo, hi));
+                    }
+                    if hi - lo > IntValue::BITS {
+                        return Err(format!(
+                            "Field range x[{}..{}] must be at most {} bits wide, found {}",
+                            lo,
+                            hi,
+                            IntValue::BITS,
+                            hi - lo
+                        ));
                     }
+                    Ok(Token::FieldRange(lo, hi))
                 }
+                'a'..='z' | '_' => self.read_word(c),
                 _ => Err(format!("Unexpected character: {}", c)),
             },
         }
     }
+
+    // Reads a decimal number whose first digit has already been read
+    fn read_number(&mut self, first: char) -> Result<IntValue, String> {
+        let mut num_str = first.to_string();
+        while let Some(&c) = self.peek_char() {
+            if c.is_ascii_digit() {
+                num_str.push(self.next_char().unwrap());
+            } else {
+                break;
+            }
+        }
+        num_str
+            .parse::<IntValue>()
+            .map_err(|_| format!("Number too large: {}", num_str))
+    }
+
+    // Reads the bit index of a field range
+    fn read_field_index(&mut self) -> Result<Field, String> {
+        match self.next_char() {
+            Some(c) if c.is_ascii_digit() => {
+                let num = self.read_number(c)?;
+                Field::try_from(num).map_err(|_| "Invalid field index number".to_string())
+            }
+            _ => Err("Expected digits in field range".to_string()),
+        }
+    }
+
+    // Reads a keyword, an `x` followed by digits, or a field name,
+    // whose first character has already been read
+    fn read_word(&mut self, first: char) -> Result<Token, String> {
+        let mut word = first.to_string();
+        while let Some(&c) = self.peek_char() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(self.next_char().unwrap());
+            } else {
+                break;
+            }
+        }
+        match word.as_str() {
+            "dup" => return Ok(Token::Dup),
+            "end" => return Ok(Token::End),
+            "check" => return Ok(Token::Check),
+            "field" => return Ok(Token::FieldKw),
+            "proj" => return Ok(Token::Proj),
+            "rev" => return Ok(Token::Rev),
+            "let" => return Ok(Token::Let),
+            _ => {}
+        }
+        if let Some(digits) = word.strip_prefix('x')
+            && !digits.is_empty()
+            && digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return match digits.parse::<u32>() {
+                Ok(index) => Ok(Token::Field(index)),
+                Err(_) => Err("Invalid field index number".to_string()),
+            };
+        }
+        Ok(Token::Ident(word))
+    }
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Result<Token, String>;
+    type Item = Result<SpannedToken, ParseError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self.next_token() {
-            Ok(Token::Eof) => None,
+            Ok(SpannedToken {
+                token: Token::Eof, ..
+            }) => None,
             Ok(tok) => Some(Ok(tok)),
             Err(e) => Some(Err(e)),
         }
     }
 }
 
+// --- Statements ---
+
+/// The relation asserted by a check statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOp {
+    Equiv,    // e1 == e2
+    Includes, // e1 <= e2
+    NotEquiv, // e1 != e2
+}
+
+impl std::fmt::Display for CheckOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckOp::Equiv => write!(f, "=="),
+            CheckOp::Includes => write!(f, "<="),
+            CheckOp::NotEquiv => write!(f, "!="),
+        }
+    }
+}
+
+/// A top-level statement of a .k2 file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expr(Exp),                  // e
+    Check(CheckOp, Exp, Exp),   // check e1 == e2, check e1 <= e2, check e1 != e2
+}
+
 // --- Parser ---
 
+/// A parsed .k2 file: the declared fields and the statements that use them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub fields: Fields,
+    pub stmts: Vec<Stmt>,
+}
+
+/// A `let` definition: an expression, or the parameters and the body tokens of a
+/// parameterized definition, which is parsed again at every use
+enum Def {
+    Expr(Exp),
+    Macro(Vec<String>, Vec<SpannedToken>),
+}
+
 pub struct Parser<'a> {
-    lexer: Peekable<Lexer<'a>>,
+    lexer: Lexer<'a>,
+    peeked: Option<SpannedToken>, // The next token of the lexer, once peeked
+    span: Span, // The span of the last token read
+    fields: Fields, // The fields declared so far
+    defs: HashMap<String, Def>, // The definitions so far
+    defining: Option<(String, Vec<String>)>, // The definition being parsed and its parameters
+    recorded: Option<Vec<SpannedToken>>, // The lexed tokens of the body of a parameterized definition
+    pending: Vec<SpannedToken>, // The tokens of an expanded definition, read before the lexer (reversed)
+    spliced: Vec<Exp>, // The arguments of expanded definitions, see `Token::Spliced`
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(lexer: Peekable<Lexer<'a>>) -> Self {
-        Parser { lexer }
+    pub fn new(lexer: Lexer<'a>) -> Self {
+        Parser {
+            span: lexer.here(),
+            lexer,
+            peeked: None,
+            fields: Fields::new(),
+            defs: HashMap::new(),
+            defining: None,
+            recorded: None,
+            pending: vec![],
+            spliced: vec![],
+        }
+    }
+
+    /// Parses a field declaration `field name : width;`, allocating the field after
+    /// the previously declared ones.
+    pub fn parse_field_decl(&mut self) -> Result<(), ParseError> {
+        self.expect(Token::FieldKw, "`field`")?;
+        let name = match self.next_token()? {
+            Token::Ident(name) => name,
+            other => {
+                return Err(self.error(format!(
+                    "Expected a field name after `field`, found {}",
+                    other
+                )));
+            }
+        };
+        let name_span = self.span;
+        match self.next_token()? {
+            Token::Colon => {}
+            other => {
+                return Err(self
+                    .error(format!(
+                        "Expected ':' after field name `{}`, found {}",
+                        name, other
+                    ))
+                    .with_help(format!("declare a field with its width, e.g. `field {} : 8;`", name)));
+            }
+        }
+        let width = match self.next_token()? {
+            Token::Zero => 0,
+            Token::One => 1,
+            Token::Num(n) => n,
+            other => {
+                return Err(self.error(format!(
+                    "Expected the width of field `{}`, found {}",
+                    name, other
+                )));
+            }
+        };
+        match self.next_token()? {
+            Token::Semicolon => {}
+            other => {
+                return Err(self
+                    .error(format!(
+                        "Expected ';' after declaration of field `{}`, found {}",
+                        name, other
+                    ))
+                    .with_help("field declarations end with ';'"));
+            }
+        }
+        if self.defs.contains_key(&name) {
+            return Err(ParseError::new(format!("`{}` is already defined", name), name_span));
+        }
+        let width = u32::try_from(width).unwrap_or(u32::MAX);
+        self.fields
+            .declare(&name, width)
+            .map_err(|message| ParseError::new(message, name_span))?;
+        Ok(())
+    }
+
+    /// Parses a definition `let name = e` or `let name(p1, ..., pn) = e`. A name must be
+    /// defined before it is used, so definitions cannot be recursive.
+    pub fn parse_let(&mut self) -> Result<(), ParseError> {
+        self.expect(Token::Let, "`let`")?;
+        let name = match self.next_token()? {
+            Token::Ident(name) => name,
+            other => return Err(self.error(format!("Expected a name after `let`, found {}", other))),
+        };
+        if self.defs.contains_key(&name) || self.fields.get(&name).is_some() {
+            return Err(self.error(format!("`{}` is already defined", name)));
+        }
+        let mut params = vec![];
+        if let Token::LParen = self.peek_token()? {
+            self.next_token()?; // Consume '('
+            loop {
+                match self.next_token()? {
+                    Token::Ident(param) if params.contains(&param) => {
+                        return Err(self.error(format!(
+                            "Duplicate parameter `{}` of `{}`",
+                            param, name
+                        )));
+                    }
+                    Token::Ident(param) => params.push(param),
+                    other => {
+                        return Err(self.error(format!(
+                            "Expected a parameter of `{}`, found {}",
+                            name, other
+                        )));
+                    }
+                }
+                match self.next_token()? {
+                    Token::Comma => {}
+                    Token::RParen => break,
+                    other => {
+                        return Err(self.error(format!(
+                            "Expected ',' or ')' after the parameters of `{}`, found {}",
+                            name, other
+                        )));
+                    }
+                }
+            }
+        }
+        self.expect(Token::Define, &format!("'=' after `let {}`", name))?;
+        // The body is parsed with placeholders for the parameters to check its syntax
+        self.defining = Some((name.clone(), params.clone()));
+        if !params.is_empty() {
+            self.recorded = Some(vec![]);
+        }
+        let body = self.parse_single_expression();
+        self.defining = None;
+        let tokens = self.recorded.take();
+        let body = body?;
+        let def = match tokens {
+            Some(tokens) => Def::Macro(params, tokens),
+            None => Def::Expr(body),
+        };
+        self.defs.insert(name, def);
+        Ok(())
+    }
+
+    // Expands the use `name(a1, ..., an)` of a parameterized definition by parsing its body,
+    // with the parameters replaced by the parsed arguments
+    fn expand(&mut self, name: &str, params: &[String], body: &[SpannedToken]) -> Result<Exp, ParseError> {
+        let name_span = self.span;
+        if *self.peek_token()? != Token::LParen {
+            return Err(ParseError::new(format!("Missing the arguments of `{}`", name), name_span)
+                .with_help(format!("`{}` takes {} arguments: `{}({})`", name, params.len(), name, params.join(", "))));
+        }
+        self.next_token()?; // Consume '('
+        let mut args = vec![];
+        loop {
+            args.push(self.parse_until()?);
+            match self.next_token()? {
+                Token::Comma => {}
+                Token::RParen => break,
+                other => {
+                    return Err(self.error(format!(
+                        "Expected ',' or ')' in the arguments of `{}`, found {}",
+                        name, other
+                    )));
+                }
+            }
+        }
+        if args.len() != params.len() {
+            return Err(ParseError::new(
+                format!(
+                    "`{}` takes {} arguments, found {}",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+                name_span.to(self.span),
+            ));
+        }
+        let first = self.spliced.len();
+        self.spliced.extend(args);
+        self.pending.push(SpannedToken {
+            token: Token::RParen,
+            span: self.span,
+        });
+        for spanned in body.iter().rev() {
+            let token = match &spanned.token {
+                Token::Ident(id) => match params.iter().position(|param| param == id) {
+                    Some(i) => Token::Spliced(first + i),
+                    None => spanned.token.clone(),
+                },
+                token => token.clone(),
+            };
+            self.pending.push(SpannedToken {
+                token,
+                span: spanned.span,
+            });
+        }
+        self.pending.push(SpannedToken {
+            token: Token::LParen,
+            span: name_span,
+        });
+        self.parse_primary()
     }
 
     /// Parses a single complete expression.
-    pub fn parse_single_expression(&mut self) -> Result<Exp, String> {
+    pub fn parse_single_expression(&mut self) -> Result<Exp, ParseError> {
         let expr = self.parse_until()?; // Start with lowest precedence
         Ok(expr)
     }
 
+    /// Parses a single statement: either an expression or a check statement.
+    pub fn parse_statement(&mut self) -> Result<Stmt, ParseError> {
+        if let Token::Check = self.peek_token()? {
+            self.next_token()?; // Consume 'check'
+            let left = self.parse_single_expression()?;
+            let op = match self.next_token()? {
+                Token::Eq => CheckOp::Equiv,
+                Token::Leq => CheckOp::Includes,
+                Token::Neq => CheckOp::NotEquiv,
+                other => {
+                    let error = self.error(format!(
+                        "Expected '==', '<=' or '!=' in check statement, found {}",
+                        other
+                    ));
+                    return Err(match other {
+                        Token::Define => error.with_help("use `==` to check equivalence"),
+                        Token::LAngle => error.with_help("use `<=` to check inclusion"),
+                        _ => error,
+                    });
+                }
+            };
+            let right = self.parse_single_expression()?;
+            return Ok(Stmt::Check(op, left, right));
+        }
+        Ok(Stmt::Expr(self.parse_single_expression()?))
+    }
+
     // Helper to get the next token
-    fn next_token(&mut self) -> Result<Token, String> {
-        self.lexer.next().unwrap_or(Ok(Token::Eof))
+    fn next_token(&mut self) -> Result<Token, ParseError> {
+        let spanned = match self.pending.pop() {
+            Some(spanned) => spanned,
+            None => {
+                let spanned = match self.peeked.take() {
+                    Some(spanned) => spanned,
+                    None => self.lexer.next_token()?,
+                };
+                if let Some(recorded) = &mut self.recorded {
+                    recorded.push(spanned.clone());
+                }
+                spanned
+            }
+        };
+        self.span = spanned.span;
+        Ok(spanned.token)
+    }
+
+    // Helper to peek at the next token and its span
+    fn peek(&mut self) -> Result<&SpannedToken, ParseError> {
+        if let Some(spanned) = self.pending.last() {
+            return Ok(spanned);
+        }
+        if self.peeked.is_none() {
+            self.peeked = Some(self.lexer.next_token()?);
+        }
+        Ok(self.peeked.as_ref().unwrap())
     }
 
     // Helper to peek at the next token
-    fn peek_token(&mut self) -> Result<&Token, String> {
-        match self.lexer.peek() {
-            Some(Ok(token)) => Ok(token),
-            Some(Err(e)) => Err(e.clone()),
-            None => Ok(&Token::Eof),
+    fn peek_token(&mut self) -> Result<&Token, ParseError> {
+        Ok(&self.peek()?.token)
+    }
+
+    // The span of the next token
+    fn peek_span(&mut self) -> Result<Span, ParseError> {
+        Ok(self.peek()?.span)
+    }
+
+    // An error at the last token read
+    fn error(&self, message: String) -> ParseError {
+        ParseError::new(message, self.span)
+    }
+
+    // Consumes the token `expected`, described by `what` in the error message
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), ParseError> {
+        match self.next_token()? {
+            token if token == expected => Ok(()),
+            other => Err(self.error(format!("Expected {}, found {}", what, other))),
         }
     }
 
     // Recursive descent parsing functions based on operator precedence:
     // Precedence (Lowest to Highest):
-    // 1. U (LTL Until) - Right associative? Usually yes.
+    // 1. U, R, S (LTL Until, Release, Since) - Right associative
     // 2. ; (Sequence) - Left associative
     // 3. +, ^, - (Union, Xor, Difference) - Left associative
     // 4. & (Intersect) - Left associative
-    // 5. !, X (Complement, LTL Next) - Prefix unary
+    // 5. !, X, F, G, Y, Z, O, H, <e>, [e], proj, rev (Complement, LTL, LDL, Projection, Converse) - Prefix unary
     // 6. * (Star) - Postfix unary
     // 7. :=, == (Assign, Test) - Non-associative? Usually require primary exprs
     // 8. Primary (Literals, Parentheses, dup, field)
 
-    /// `parse_until` handles 'U' and 'R'.     
+    /// `parse_until` handles 'U', 'R' and 'S'.     
     /// This function desugars `e1 R e2 ≡ ¬(¬e1 U ¬e2)`.
-    fn parse_until(&mut self) -> Result<Exp, String> {
+    fn parse_until(&mut self) -> Result<Exp, ParseError> {
         let left = self.parse_sequence()?;
         match self.peek_token()? {
             Token::LtlU => {
@@ -239,23 +884,30 @@ impl<'a> Parser<'a> {
                 let until = Expr::ltl_until(not_left, not_right);
                 Ok(Expr::complement(until))
             }
+            Token::LtlS => {
+                self.next_token()?; // Consume 'S'
+                let right = self.parse_until()?; // Recurse for right associativity
+                Ok(Expr::ltl_since(left, right))
+            }
             _ => Ok(left),
         }
     }
 
     // parse_sequence handles ';'
-    fn parse_sequence(&mut self) -> Result<Exp, String> {
+    fn parse_sequence(&mut self) -> Result<Exp, ParseError> {
         let mut left = self.parse_additive()?;
         while let Ok(Token::Semicolon) = self.peek_token() {
             self.next_token()?; // Consume ';'
+            let start = self.peek_span()?;
             let right = self.parse_additive()?;
+            no_past(&right, "on the right of ';'", start.to(self.span))?;
             left = Expr::sequence(left, right);
         }
         Ok(left)
     }
 
     // parse_additive handles '+', '^', '-'
-    fn parse_additive(&mut self) -> Result<Exp, String> {
+    fn parse_additive(&mut self) -> Result<Exp, ParseError> {
         let mut left = self.parse_intersect()?;
         loop {
             match self.peek_token() {
@@ -281,7 +933,7 @@ impl<'a> Parser<'a> {
     }
 
     // parse_intersect handles '&'
-    fn parse_intersect(&mut self) -> Result<Exp, String> {
+    fn parse_intersect(&mut self) -> Result<Exp, ParseError> {
         let mut left = self.parse_unary()?;
         while let Ok(Token::And) = self.peek_token() {
             self.next_token()?; // Consume '&'
@@ -291,11 +943,14 @@ impl<'a> Parser<'a> {
         Ok(left)
     }
 
-    /// `parse_unary` handles prefix '!', 'X', 'F', 'G' and postfix '*'.      
+    /// `parse_unary` handles prefix '!', 'X', 'F', 'G', 'Y', 'Z', 'O', 'H', '<e>', '[e]', 'proj {...}', 'rev' and postfix '*'.      
     /// This function desugars the derived LTL operators:
     /// - `F e ≡ true U e`
     /// - `G e ≡ ¬(true U ¬e)`
-    fn parse_unary(&mut self) -> Result<Exp, String> {
+    /// - `Z e ≡ ¬Y ¬e`
+    /// - `O e ≡ true S e`
+    /// - `H e ≡ ¬O ¬e`
+    fn parse_unary(&mut self) -> Result<Exp, ParseError> {
         match self.peek_token()? {
             Token::Not => {
                 self.next_token()?; // Consume '!'
@@ -307,6 +962,19 @@ impl<'a> Parser<'a> {
                 let expr = self.parse_unary()?; // Apply to the result of next level
                 Ok(Expr::ltl_next(expr))
             }
+            Token::Proj => {
+                self.next_token()?; // Consume 'proj'
+                let fields = self.parse_field_set()?;
+                let start = self.peek_span()?;
+                let expr = self.parse_unary()?;
+                no_past(&expr, "under 'proj'", start.to(self.span))?;
+                Ok(Expr::proj(fields, expr))
+            }
+            Token::Rev => {
+                self.next_token()?; // Consume 'rev'
+                let expr = self.parse_unary()?;
+                Ok(Expr::rev(expr))
+            }
             Token::LtlF => {
                 self.next_token()?; // Consume 'F'
                 let expr = self.parse_unary()?;
@@ -321,13 +989,53 @@ impl<'a> Parser<'a> {
                 let until = Expr::ltl_until(Expr::top(), not_expr);
                 Ok(Expr::complement(until))
             }
+            Token::LtlY => {
+                self.next_token()?; // Consume 'Y'
+                let expr = self.parse_unary()?;
+                Ok(Expr::ltl_yesterday(expr))
+            }
+            Token::LtlZ => {
+                self.next_token()?; // Consume 'Z'
+                let expr = self.parse_unary()?;
+                Ok(Expr::ltl_weak_yesterday(expr))
+            }
+            Token::LtlO => {
+                self.next_token()?; // Consume 'O'
+                let expr = self.parse_unary()?;
+                Ok(Expr::ltl_once(expr))
+            }
+            Token::LtlH => {
+                self.next_token()?; // Consume 'H'
+                let expr = self.parse_unary()?;
+                Ok(Expr::ltl_historically(expr))
+            }
+            Token::LAngle => {
+                self.next_token()?; // Consume '<'
+                let path = self.parse_until()?;
+                self.expect(Token::RAngle, "'>' after the path of '<...>'")?;
+                let start = self.peek_span()?;
+                let expr = self.parse_unary()?;
+                no_past(&expr, "after an LDL modality", start.to(self.span))?;
+                Ok(Expr::ldl_diamond(path, expr))
+            }
+            Token::LBracket => {
+                self.next_token()?; // Consume '['
+                let path = self.parse_until()?;
+                self.expect(Token::RBracket, "']' after the path of '[...]'")?;
+                let start = self.peek_span()?;
+                let expr = self.parse_unary()?;
+                no_past(&expr, "after an LDL modality", start.to(self.span))?;
+                Ok(Expr::ldl_box(path, expr))
+            }
             _ => {
                 // If not a prefix operator, parse the primary expression
+                let start = self.peek_span()?;
                 let mut expr = self.parse_primary()?;
                 // Check for postfix '*'
                 // Allow multiple postfix stars potentially? Loop for e***
                 while let Ok(Token::Star) = self.peek_token() {
                     self.next_token()?; // Consume '*'
+                    no_past(&expr, "under '*'", start.to(self.span))?;
                     expr = Expr::star(expr);
                 }
                 Ok(expr)
@@ -335,8 +1043,110 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses the set of bits `{x0, x[2..4], name}` kept by a projection
+    fn parse_field_set(&mut self) -> Result<Vec<Field>, ParseError> {
+        match self.next_token()? {
+            Token::LBrace => {}
+            other => {
+                return Err(self
+                    .error(format!("Expected '{{' after `proj`, found {}", other))
+                    .with_help("write the kept fields in braces, e.g. `proj {x0, sw} e`"));
+            }
+        }
+        let mut fields = vec![];
+        if let Token::RBrace = self.peek_token()? {
+            self.next_token()?;
+            return Ok(fields);
+        }
+        loop {
+            match self.next_token()? {
+                Token::Field(idx) => fields.push(idx),
+                Token::FieldRange(lo, hi) => fields.extend(lo..hi),
+                Token::Ident(name) => match self.fields.get(&name) {
+                    Some(decl) => fields.extend(decl.start..decl.start + decl.width),
+                    None => return Err(self.error(format!("Undeclared field `{}`", name))),
+                },
+                other => {
+                    return Err(self.error(format!("Expected a field in projection, found {}", other)));
+                }
+            }
+            match self.next_token()? {
+                Token::Comma => {}
+                Token::RBrace => return Ok(fields),
+                other => {
+                    return Err(self.error(format!(
+                        "Expected ',' or '}}' in projection, found {}",
+                        other
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Parses the `== value` or `:= value` following a field of `width` bits starting at `start`.
+    /// Single-bit fields produce `Test`/`Assign`, wider fields produce `TestInt`/`AssignInt`.
+    /// The `name` of the field is only used in error messages.
+    fn parse_field_op(&mut self, name: &str, start: Field, width: u32) -> Result<Exp, ParseError> {
+        match self.next_token()? {
+            Token::Eq => {
+                let value = self.parse_field_value(name, "==", width)?;
+                if width == 1 {
+                    Ok(Expr::test(start, value == 1))
+                } else {
+                    Ok(Expr::test_int(start, width, value))
+                }
+            }
+            Token::Assign => {
+                let value = self.parse_field_value(name, ":=", width)?;
+                if width == 1 {
+                    Ok(Expr::assign(start, value == 1))
+                } else {
+                    Ok(Expr::assign_int(start, width, value))
+                }
+            }
+            other => {
+                let error = self.error(format!(
+                    "Expected '==' or ':=' after field `{}`, found {}",
+                    name, other
+                ));
+                Err(match other {
+                    Token::Define => error.with_help("use `==` to test a field or `:=` to assign it"),
+                    _ => error,
+                })
+            }
+        }
+    }
+
+    // Parses the value after `op`, checking that it fits in `width` bits
+    fn parse_field_value(&mut self, name: &str, op: &str, width: u32) -> Result<IntValue, ParseError> {
+        let max = if width < 64 { (1 << width) - 1 } else { IntValue::MAX };
+        let bits = if width == 1 { "1 bit".to_string() } else { format!("{} bits", width) };
+        let value = match self.next_token()? {
+            Token::Zero => 0,
+            Token::One => 1,
+            Token::Num(n) if width > 1 => n,
+            other @ Token::Num(_) => {
+                return Err(self
+                    .error(format!("Expected 0 or 1 after '{}', found {}", op, other))
+                    .with_help(format!("`{}` has {}, so its values are 0 and 1", name, bits)));
+            }
+            other => {
+                let action = if op == ":=" { "assigns" } else { "tests for" };
+                return Err(self
+                    .error(format!("Expected a value after '{}', found {}", op, other))
+                    .with_help(format!("`{} {} v` {} a value v from 0 to {}", name, op, action, max)));
+            }
+        };
+        if value > max {
+            return Err(self
+                .error(format!("Value {} does not fit in {}", value, bits))
+                .with_help(format!("`{}` has {}, so its values are 0 to {}", name, bits, max)));
+        }
+        Ok(value)
+    }
+
     // parse_primary handles literals, parentheses, field ops, dup
-    fn parse_primary(&mut self) -> Result<Exp, String> {
+    fn parse_primary(&mut self) -> Result<Exp, ParseError> {
         let token = self.next_token()?;
         match token {
             Token::Zero => Ok(Expr::zero()),
@@ -344,69 +1154,141 @@ impl<'a> Parser<'a> {
             Token::Top => Ok(Expr::top()),
             Token::Dup => Ok(Expr::dup()),
             Token::End => Ok(Expr::end()),
-            Token::Field(idx) => {
-                // Look ahead for '==' or ':='
-                match self.peek_token()? {
-                    Token::Eq => {
-                        self.next_token()?; // Consume '=='
-                        match self.next_token()? {
-                            Token::Zero => Ok(Expr::test(idx, false)),
-                            Token::One => Ok(Expr::test(idx, true)),
-                            other => Err(format!("Expected 0 or 1 after '==', found {:?}", other)),
-                        }
+            Token::Field(idx) => self.parse_field_op(&format!("x{}", idx), idx, 1),
+            Token::FieldRange(lo, hi) => {
+                self.parse_field_op(&format!("x[{}..{}]", lo, hi), lo, hi - lo)
+            }
+            Token::Ident(name) => {
+                if let Some((defining, params)) = &self.defining {
+                    if params.contains(&name) {
+                        return Ok(Expr::zero()); // Placeholder for a parameter
+                    }
+                    if *defining == name {
+                        return Err(self.error(format!("Recursive definition of `{}`", name)));
+                    }
+                }
+                if let Some(decl) = self.fields.get(&name) {
+                    let (start, width) = (decl.start, decl.width);
+                    return self.parse_field_op(&name, start, width);
+                }
+                match self.defs.get(&name) {
+                    Some(Def::Expr(expr)) => Ok(expr.clone()),
+                    Some(Def::Macro(params, body)) => {
+                        let (params, body) = (params.clone(), body.clone());
+                        self.expand(&name, &params, &body)
                     }
-                    Token::Assign => {
-                        self.next_token()?; // Consume ':='
-                        match self.next_token()? {
-                            Token::Zero => Ok(Expr::assign(idx, false)),
-                            Token::One => Ok(Expr::assign(idx, true)),
-                            other => Err(format!("Expected 0 or 1 after ':=', found {:?}", other)),
+                    None => {
+                        let span = self.span;
+                        match self.peek_token()? {
+                            Token::Eq | Token::Assign => Err(ParseError::new(
+                                format!("Undeclared field `{}`", name),
+                                span,
+                            )
+                            .with_help(format!("declare it before its first use, e.g. `field {} : 8;`", name))),
+                            Token::Define => Err(ParseError::new(
+                                format!("Undefined name `{}`", name),
+                                span,
+                            )
+                            .with_help(format!("write `let {} = ...` to define it", name))),
+                            _ => Err(ParseError::new(format!("Undefined name `{}`", name), span)),
                         }
                     }
-                    _ => Err(format!("Expected '==' or ':=' after field x{}", idx)),
                 }
             }
+            Token::Spliced(i) => Ok(self.spliced[i].clone()),
+            Token::FieldKw => Err(self.error(
+                "Field declarations must appear at the top level, not inside an expression"
+                    .to_string(),
+            )),
+            Token::Let => Err(self.error(
+                "Definitions must appear at the top level, not inside an expression".to_string(),
+            )),
             Token::LParen => {
+                let open = self.span;
                 let expr = self.parse_until()?; // Parse expression within parentheses, start from lowest precedence (U)
                 match self.next_token()? {
                     Token::RParen => Ok(expr),
-                    other => Err(format!(
-                        "Expected closing parenthesis ')', found {:?}",
-                        other
-                    )),
+                    other => Err(self
+                        .error(format!("Expected closing parenthesis ')', found {}", other))
+                        .with_help(format!(
+                            "the '(' at line {}, column {} is not closed",
+                            open.line, open.column
+                        ))),
                 }
             }
-            Token::Eof => Err("Unexpected end of input in primary expression".to_string()),
-            other => Err(format!(
-                "Unexpected token in primary expression: {:?}",
-                other
-            )),
+            Token::Define => Err(self
+                .error("Expected an expression, found '='".to_string())
+                .with_help("`=` only appears in `let` definitions; use `==` to test a field or `:=` to assign it")),
+            other => Err(self.error(format!("Expected an expression, found {}", other))),
         }
     }
 }
 
+// Past operators look back from the position where they are evaluated, which is only
+// tracked for the boolean and LTL operators (see `Aut::past_resolve`)
+fn no_past(expr: &Expr, place: &str, span: Span) -> Result<(), ParseError> {
+    if expr.has_past() {
+        return Err(ParseError::new(
+            format!("Past operators (Y, Z, S, O, H) cannot be used {}", place),
+            span,
+        ));
+    }
+    Ok(())
+}
+
 // --- Main Parsing Functions ---
 
 /// Parses a string containing multiple NetKAT expressions (separated by whitespace/newlines/comments)
 /// into a Vec<Exp>.
-pub fn parse_expressions(input: &str) -> Result<Vec<Exp>, String> {
-    let lexer = Lexer::new(input).peekable();
-    let mut parser = Parser::new(lexer);
+pub fn parse_expressions(input: &str) -> Result<Vec<Exp>, ParseError> {
     let mut expressions = Vec::new();
+    for stmt in parse_source(input, false)?.stmts {
+        if let Stmt::Expr(expr) = stmt {
+            expressions.push(expr);
+        }
+    }
+    Ok(expressions)
+}
+
+/// Parses a string containing multiple statements (expressions and check statements)
+/// into a Vec<Stmt>.
+pub fn parse_statements(input: &str) -> Result<Vec<Stmt>, ParseError> {
+    Ok(parse_program(input)?.stmts)
+}
+
+/// Parses a string containing field declarations and statements into a Program.
+/// A field must be declared before it is used.
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    parse_source(input, true)
+}
+
+// Parses a program, rejecting check statements unless `checks` is set
+fn parse_source(input: &str, checks: bool) -> Result<Program, ParseError> {
+    let mut parser = Parser::new(Lexer::new(input));
+    let mut stmts = Vec::new();
     loop {
         match parser.peek_token()? {
             Token::Eof => break,
-            _ => expressions.push(parser.parse_single_expression()?),
+            Token::FieldKw => parser.parse_field_decl()?,
+            Token::Let => parser.parse_let()?,
+            Token::Check if !checks => {
+                let span = parser.peek_span()?;
+                return Err(ParseError::new("Unexpected check statement", span));
+            }
+            _ => stmts.push(parser.parse_statement()?),
         }
     }
-    Ok(expressions)
+    Ok(Program {
+        fields: parser.fields,
+        stmts,
+    })
 }
 
-pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
     let lexer = Lexer::new(input);
     let mut tokens = Vec::new();
     for token in lexer {
-        tokens.push(token?);
+        tokens.push(token?.token);
     }
     Ok(tokens)
 }
@@ -416,7 +1298,7 @@ mod tests {
     use super::*;
 
     fn parse(s: &str) -> Result<Exp, String> {
-        let result = parse_expressions(s)?;
+        let result = parse_expressions(s).map_err(|e| e.message)?;
         if result.len() == 1 {
             Ok(result.into_iter().next().unwrap())
         } else if result.is_empty() {
@@ -479,6 +1361,28 @@ mod tests {
         assert!(parse("x1 == T").is_err(), "Invalid value T");
     }
 
+    #[test]
+    fn test_int_field_ops() {
+        assert_eq!(parse("x[0..4] == 5"), Ok(Expr::test_int(0, 4, 5)));
+        assert_eq!(parse("x[4..12] := 255"), Ok(Expr::assign_int(4, 8, 255)));
+        assert_eq!(parse("x[2..4] == 0"), Ok(Expr::test_int(2, 2, 0)));
+        assert_eq!(parse("x[3..4] := 1"), Ok(Expr::assign(3, true)));
+        assert_eq!(
+            parse("x[0..2] == 3 ; x[2..4] := 2"),
+            Ok(Expr::sequence(Expr::test_int(0, 2, 3), Expr::assign_int(2, 2, 2)))
+        );
+        assert!(parse("x[0..2] == 4").is_err(), "Value does not fit");
+        assert!(parse("x[2..2] == 0").is_err(), "Empty range");
+        assert_eq!(parse("x[0..64] == 1"), Ok(Expr::test_int(0, 64, 1)));
+        assert_eq!(
+            parse("x[0..70] == 1"),
+            Err("Field range x[0..70] must be at most 64 bits wide, found 70".to_string())
+        );
+        assert!(parse("x[0..2 == 0").is_err(), "Unclosed range");
+        assert!(parse("x0 == 2").is_err(), "Single bit field");
+        assert!(parse("5").is_err(), "Number is not an expression");
+    }
+
     #[test]
     fn test_simple_binary_ops() {
         assert_eq!(
@@ -520,6 +1424,13 @@ mod tests {
             parse("(x0==0)**"),
             Ok(Expr::star(Expr::star(Expr::test(0, false))))
         );
+        assert_eq!(
+            parse("rev x0:=1* ; dup"),
+            Ok(Expr::sequence(
+                Expr::rev(Expr::star(Expr::assign(0, true))),
+                Expr::dup()
+            ))
+        );
     }
 
     #[test]
@@ -627,6 +1538,70 @@ mod tests {
                 Expr::complement(Expr::ltl_until(Expr::top(), Expr::test(0, false)))
             )))
         );
+
+        // Past operators
+        assert_eq!(parse("Y x0==0"), Ok(Expr::ltl_yesterday(Expr::test(0, false))));
+        assert_eq!(
+            parse("Z x0==0"),
+            Ok(Expr::complement(Expr::ltl_yesterday(Expr::complement(
+                Expr::test(0, false)
+            ))))
+        );
+        assert_eq!(
+            parse("x0==0 S x1==1 U x2==0"),
+            Ok(Expr::ltl_since(
+                Expr::test(0, false),
+                Expr::ltl_until(Expr::test(1, true), Expr::test(2, false))
+            ))
+        );
+        assert_eq!(
+            parse("O x0==0"),
+            Ok(Expr::ltl_since(Expr::top(), Expr::test(0, false)))
+        );
+        assert_eq!(
+            parse("H x0==0"),
+            Ok(Expr::complement(Expr::ltl_since(
+                Expr::top(),
+                Expr::complement(Expr::test(0, false))
+            )))
+        );
+        assert_eq!(
+            parse("Y x0==0 ; dup"),
+            Ok(Expr::sequence(
+                Expr::ltl_yesterday(Expr::test(0, false)),
+                Expr::dup()
+            ))
+        );
+        assert!(parse("dup ; Y x0==0").is_err());
+        assert!(parse("(Y x0==0)*").is_err());
+        assert!(parse("proj {x0} H x0==0").is_err());
+
+        // LDL modalities
+        assert_eq!(
+            parse("<x0==1> x1==0"),
+            Ok(Expr::ldl_diamond(Expr::test(0, true), Expr::test(1, false)))
+        );
+        assert_eq!(
+            parse("[(x0==1; dup)*] X x1==0 ; dup"),
+            Ok(Expr::sequence(
+                Expr::ldl_box(
+                    Expr::star(Expr::sequence(Expr::test(0, true), Expr::dup())),
+                    Expr::ltl_next(Expr::test(1, false))
+                ),
+                Expr::dup()
+            ))
+        );
+        assert_eq!(
+            parse_statements("check x0==1 <= <dup> T"),
+            Ok(vec![Stmt::Check(
+                CheckOp::Includes,
+                Expr::test(0, true),
+                Expr::ldl_diamond(Expr::dup(), Expr::top())
+            )])
+        );
+        assert!(parse("<x0==1 x1==0").is_err());
+        assert!(parse("[x0==1 > x1==0").is_err());
+        assert!(parse("[dup] Y x0==1").is_err());
     }
 
     #[test]
@@ -662,6 +1637,221 @@ mod tests {
         assert!(parse(";").is_err(), "Requires expressions around ;");
     }
 
+    #[test]
+    fn test_error_spans() {
+        let span = |start, end, line, column| Span { start, end, line, column };
+        let error = parse_statements("field sw : 2;\nsw = 1").unwrap_err();
+        assert_eq!(error.message, "Expected '==' or ':=' after field `sw`, found '='");
+        assert_eq!(error.span, span(17, 18, 2, 4));
+        assert_eq!(
+            error.help.as_deref(),
+            Some("use `==` to test a field or `:=` to assign it")
+        );
+        assert_eq!(
+            error.render("field sw : 2;\nsw = 1", "net.k2"),
+            "error: Expected '==' or ':=' after field `sw`, found '='\n \
+             --> net.k2:2:4\n  |\n2 | sw = 1\n  |    ^\n  \
+             = help: use `==` to test a field or `:=` to assign it"
+        );
+
+        let error = parse_statements("x0 == 1 ;\n\tx1 :=").unwrap_err();
+        assert_eq!(error.message, "Expected a value after ':=', found end of input");
+        assert_eq!(error.span, span(16, 16, 2, 7));
+        assert_eq!(
+            error.help.as_deref(),
+            Some("`x1 := v` assigns a value v from 0 to 1")
+        );
+        assert!(error.render("x0 == 1 ;\n\tx1 :=", "-").contains("\n  | \t     ^"));
+
+        let error = parse_statements("field sw : 2; sw == 4").unwrap_err();
+        assert_eq!(error.span, span(20, 21, 1, 21));
+        assert_eq!(error.help.as_deref(), Some("`sw` has 2 bits, so its values are 0 to 3"));
+        assert_eq!(parse_statements("dup +\n  # 1").unwrap_err().span, span(8, 9, 2, 3));
+        assert_eq!(parse_statements("dup ; Y x0==0").unwrap_err().span, span(6, 13, 1, 7));
+        assert_eq!(parse_statements("(dup + x0==1").unwrap_err().span, span(12, 12, 1, 13));
+        assert_eq!(
+            parse_statements("let f(s) = s ; dup\nf(dup, 1)").unwrap_err().span,
+            span(19, 28, 2, 1)
+        );
+        let error = parse_statements("check dup = dup").unwrap_err();
+        assert_eq!(error.span, span(10, 11, 1, 11));
+        assert_eq!(error.help.as_deref(), Some("use `==` to check equivalence"));
+        assert_eq!(error.to_string(), "1:11: Expected '==', '<=' or '!=' in check statement, found '='");
+        assert_eq!(parse_statements("dup ; x[0..70] == 1").unwrap_err().span, span(6, 14, 1, 7));
+    }
+
+    #[test]
+    fn test_check_statements() {
+        assert_eq!(
+            parse_statements("check x0==1 == x0==1 + 0\n check dup <= T\n check 0 != 1"),
+            Ok(vec![
+                Stmt::Check(
+                    CheckOp::Equiv,
+                    Expr::test(0, true),
+                    Expr::union(Expr::test(0, true), Expr::zero())
+                ),
+                Stmt::Check(CheckOp::Includes, Expr::dup(), Expr::top()),
+                Stmt::Check(CheckOp::NotEquiv, Expr::zero(), Expr::one()),
+            ])
+        );
+        assert_eq!(
+            parse_statements("x0==1 // expression\n check !x0==1 != x0==1"),
+            Ok(vec![
+                Stmt::Expr(Expr::test(0, true)),
+                Stmt::Check(
+                    CheckOp::NotEquiv,
+                    Expr::complement(Expr::test(0, true)),
+                    Expr::test(0, true)
+                ),
+            ])
+        );
+        assert!(parse_statements("check 0").is_err(), "Requires a relation");
+        assert!(parse_statements("check 0 ==").is_err(), "Requires a right-hand side");
+        assert!(parse_statements("check 0 < 1").is_err(), "No strict inclusion");
+        assert!(parse_expressions("check 0 == 0").is_err());
+    }
+
+    #[test]
+    fn test_field_declarations() {
+        let program = parse_program(
+            "field sw : 4; field up : 1;\n field dst : 32;\n sw == 5 ; up := 1 ; dst := 1\n check sw == 3 <= T",
+        )
+        .unwrap();
+        assert_eq!(program.fields.num_bits(), 37);
+        assert_eq!(
+            program.stmts,
+            vec![
+                Stmt::Expr(Expr::sequence(
+                    Expr::sequence(Expr::test_int(0, 4, 5), Expr::assign(4, true)),
+                    Expr::assign_int(5, 32, 1)
+                )),
+                Stmt::Check(CheckOp::Includes, Expr::test_int(0, 4, 3), Expr::top()),
+            ]
+        );
+        // Declared fields can be mixed with explicit bits
+        assert_eq!(
+            parse_expressions("field sw : 2; sw == 1 + x2 == 1"),
+            Ok(vec![Expr::union(Expr::test_int(0, 2, 1), Expr::test(2, true))])
+        );
+        assert_eq!(
+            parse_statements("sw == 1").unwrap_err().message,
+            "Undeclared field `sw`"
+        );
+        assert!(parse_statements("sw == 1 field sw : 2;").is_err(), "Use before declaration");
+        assert!(parse_statements("field sw : 2; sw == 4").is_err(), "Value does not fit");
+        assert!(parse_statements("field sw : 2; field sw : 3;").is_err(), "Duplicate field");
+        assert!(parse_statements("field sw : 0;").is_err(), "Empty field");
+        assert!(parse_statements("field sw : 4").is_err(), "Missing semicolon");
+        assert!(parse_statements("field dup : 4;").is_err(), "Keyword as name");
+        assert!(parse_statements("field x3 : 4;").is_err(), "Bit as name");
+        assert!(parse_statements("0 ; field sw : 4;").is_err(), "Declaration inside expression");
+    }
+
+    #[test]
+    fn test_definitions() {
+        assert_eq!(
+            parse_expressions("let a = x0 == 1 ; dup\n a + X a"),
+            Ok(vec![Expr::union(
+                Expr::sequence(Expr::test(0, true), Expr::dup()),
+                Expr::ltl_next(Expr::sequence(Expr::test(0, true), Expr::dup()))
+            )])
+        );
+        // Arguments are substituted as parenthesized expressions
+        assert_eq!(
+            parse_expressions("field sw : 2; let at(s) = s ; T\n let hop(s, t) = at(s) & X t\n hop(sw == 1, x2 == 0 + dup)"),
+            Ok(vec![Expr::intersect(
+                Expr::sequence(Expr::test_int(0, 2, 1), Expr::top()),
+                Expr::ltl_next(Expr::union(Expr::test(2, false), Expr::dup()))
+            )])
+        );
+        // Definitions are expanded in check statements too, and the parameters shadow other names
+        assert_eq!(
+            parse_statements("let a = dup\n let f(a) = a ; a\n check f(a) == dup ; dup"),
+            Ok(vec![Stmt::Check(
+                CheckOp::Equiv,
+                Expr::sequence(Expr::dup(), Expr::dup()),
+                Expr::sequence(Expr::dup(), Expr::dup())
+            )])
+        );
+        assert_eq!(parse_statements("a").unwrap_err().message, "Undefined name `a`");
+        assert_eq!(
+            parse_statements("let a = a + dup").unwrap_err().message,
+            "Recursive definition of `a`"
+        );
+        assert!(parse_statements("a let a = dup").is_err(), "Use before definition");
+        assert!(parse_statements("let a = dup let a = 1").is_err(), "Duplicate definition");
+        assert!(parse_statements("field a : 1; let a = dup").is_err(), "Field and definition");
+        assert!(parse_statements("let a = dup field a : 1;").is_err(), "Definition and field");
+        assert!(parse_statements("let f(s) = s ; s f(dup, dup)").is_err(), "Wrong arity");
+        assert!(parse_statements("let f(s, s) = s").is_err(), "Duplicate parameter");
+        assert!(parse_statements("let f(s) = s ; dup\n f + 1").is_err(), "Missing arguments");
+        assert!(parse_statements("let f(s) = s +").is_err(), "Incomplete body");
+        assert!(parse_statements("dup + let a = 1").is_err(), "Definition inside expression");
+    }
+
+    #[test]
+    fn test_print_round_trip() {
+        for (input, printed) in [
+            ("T ; 0 ; 1", "T ; 0 ; 1"),
+            ("(x0==0 ; x1==1) + x2==0", "(x0 == 0 ; x1 == 1) + x2 == 0"),
+            ("x0==0 ; (x1==1 + x2==0)", "x0 == 0 ; x1 == 1 + x2 == 0"),
+            ("x0==0 ; (x1==1 ; x2==0)", "x0 == 0 ; (x1 == 1 ; x2 == 0)"),
+            ("(x0==0 + x1==1) - (dup ^ end)", "x0 == 0 + x1 == 1 - (dup ^ end)"),
+            ("(x0==0 & x1==1) & !(dup + 1)", "x0 == 0 & x1 == 1 & !(dup + 1)"),
+            ("(x0:=1)* ; (!dup)* ; dup**", "(x0 := 1)* ; (!dup)* ; dup**"),
+            ("!(x0==1)* & X (dup ; T)", "!(x0 == 1)* & X (dup ; T)"),
+            ("(x0==0 U x1==1) U (dup U end)", "(x0 == 0 U x1 == 1) U dup U end"),
+            ("F x0==1 ; T U !G !end", "F x0 == 1 ; T U !G !end"),
+            ("!(!x0==1 U !x1==0) + x0==1 R x1==1", "(x0 == 1 R x1 == 0) + x0 == 1 R x1 == 1"),
+            ("x0==1 R (x1==1 R end)", "x0 == 1 R x1 == 1 R end"),
+            ("Z x0==1 & O x1==1 & H Y dup", "Z x0 == 1 & O x1 == 1 & H Y dup"),
+            ("x0==1 S x1==1 U end", "x0 == 1 S x1 == 1 U end"),
+            ("<(x0==1 ; dup)*> [dup U end] T", "<(x0 == 1 ; dup)*> [dup U end] T"),
+            ("rev (x0:=1 ; dup) ; proj {x1, x[2..4]} dup*", "rev (x0 := 1 ; dup) ; proj {x1, x2, x3} dup*"),
+            ("x[0..4] == 5 ; x[4..12] := 255", "x[0..4] == 5 ; x[4..12] := 255"),
+        ] {
+            let expr = parse(input).unwrap();
+            let output = expr.to_string();
+            assert_eq!(output, printed);
+            assert_eq!(parse(&output), Ok(expr), "{}", output);
+        }
+        // Declared fields are printed by name
+        let program = parse_program("field sw : 4; field up : 1;\n proj {sw} (up := 1 ; sw == 3)*").unwrap();
+        let Stmt::Expr(expr) = &program.stmts[0] else { unreachable!() };
+        let output = expr.display(&program.fields).to_string();
+        assert_eq!(output, "proj {sw} (up := 1 ; sw == 3)*");
+        assert_eq!(
+            parse_expressions(&format!("field sw : 4; field up : 1; {}", output)),
+            Ok(vec![expr.clone()])
+        );
+    }
+
+    #[test]
+    fn test_projection() {
+        assert_eq!(
+            parse("proj {x0, x[2..4]} dup"),
+            Ok(Expr::proj(vec![0, 2, 3], Expr::dup()))
+        );
+        assert_eq!(parse("proj {} 1"), Ok(Expr::proj(vec![], Expr::one())));
+        assert_eq!(
+            parse("proj {x1} dup ; x0 == 1"),
+            Ok(Expr::sequence(
+                Expr::proj(vec![1], Expr::dup()),
+                Expr::test(0, true)
+            ))
+        );
+        assert_eq!(
+            parse_expressions("field sw : 2; field up : 1; proj {sw} (up := 1; dup)"),
+            Ok(vec![Expr::proj(
+                vec![0, 1],
+                Expr::sequence(Expr::assign(2, true), Expr::dup())
+            )])
+        );
+        assert!(parse("proj x0 dup").is_err(), "Missing braces");
+        assert!(parse("proj {x0 x1} dup").is_err(), "Missing comma");
+        assert!(parse("proj {sw} dup").is_err(), "Undeclared field");
+    }
+
     #[test]
     fn temp() {
         println!("{:?}", parse_expressions("0 //\n 1"));
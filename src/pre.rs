@@ -3,3 +3,6 @@ pub type Field = u32;
 
 /// Represents a value (0 or 1)
 pub type Value = bool;
+
+/// Represents the value of a multi-bit field, e.g. a switch id or a port number
+pub type IntValue = u64;
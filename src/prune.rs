@@ -0,0 +1,172 @@
+// Forward-backward pruning of explicit automata.
+//
+// The forward pass computes, for each state, the packets that can be current when a string
+// reaches it (starting from all packets at the root). The backward pass computes the packets
+// from which the state can still accept a string. A transition s -> t then only needs the
+// packet pairs (p, q) with p forward-reachable at s and q co-reachable at t, and an epsilon
+// only the pairs (p, q) with p forward-reachable at s. After pruning, every packet pair in
+// every SPP occurs in some accepted string.
+
+use crate::aut::{ExplicitAut, ST};
+use crate::sp::SP;
+use crate::spp::{SPP, SPPstore};
+use std::collections::HashMap;
+
+/// Computes the packets that can be current at each state, starting from all packets at the root
+fn forward(explicit: &ExplicitAut, store: &mut SPPstore) -> Vec<SP> {
+    let mut reach = vec![store.sp.zero; explicit.num_states()];
+    let mut todo = vec![(0, store.sp.one)];
+    while let Some((state, sp)) = todo.pop() {
+        let new = store.sp.difference(sp, reach[state]);
+        if new == store.sp.zero {
+            continue;
+        }
+        reach[state] = store.sp.union(reach[state], new);
+        for (&target, &spp) in explicit.transitions[state].get_transitions() {
+            let image = store.push(new, spp);
+            todo.push((target, image));
+        }
+    }
+    reach
+}
+
+/// Computes the packets from which each state accepts some string
+fn backward(explicit: &ExplicitAut, store: &mut SPPstore) -> Vec<SP> {
+    let n = explicit.num_states();
+    let mut predecessors: Vec<Vec<(usize, SPP)>> = vec![vec![]; n];
+    for (state, st) in explicit.transitions.iter().enumerate() {
+        for (&target, &spp) in st.get_transitions() {
+            predecessors[target].push((state, spp));
+        }
+    }
+    let mut coreach = vec![store.sp.zero; n];
+    let mut todo = vec![];
+    for (state, &epsilon) in explicit.epsilons.iter().enumerate() {
+        let accepting = store.pull(epsilon, store.sp.one);
+        todo.push((state, accepting));
+    }
+    while let Some((state, sp)) = todo.pop() {
+        let new = store.sp.difference(sp, coreach[state]);
+        if new == store.sp.zero {
+            continue;
+        }
+        coreach[state] = store.sp.union(coreach[state], new);
+        for &(source, spp) in &predecessors[state] {
+            let preimage = store.pull(spp, new);
+            todo.push((source, preimage));
+        }
+    }
+    coreach
+}
+
+/// Restricts the SPPs of `explicit` to the packet pairs that occur in some accepted string,
+/// and removes the states that no longer occur in any.
+/// An empty automaton prunes down to no states at all.
+pub fn prune(explicit: &ExplicitAut, store: &mut SPPstore) -> ExplicitAut {
+    let mut pruned = ExplicitAut {
+        states: vec![],
+        labels: vec![],
+        transitions: vec![],
+        epsilons: vec![],
+        complete: explicit.complete,
+    };
+    if explicit.num_states() == 0 {
+        return pruned;
+    }
+    let reach = forward(explicit, store);
+    let coreach = backward(explicit, store);
+    if store.sp.intersect(reach[0], coreach[0]) == store.sp.zero {
+        return pruned;
+    }
+
+    // Renumber the remaining states in breadth-first order from the root
+    let mut number: HashMap<usize, usize> = HashMap::from([(0, 0)]);
+    let mut order = vec![0];
+    let mut i = 0;
+    while i < order.len() {
+        let state = order[i];
+        let inputs = store.ibwd(reach[state]);
+        let epsilon = store.intersect(explicit.epsilons[state], inputs);
+        let mut transitions = vec![];
+        for (&target, &spp) in explicit.transitions[state].get_transitions() {
+            let outputs = store.ifwd(coreach[target]);
+            let spp = store.intersect(spp, inputs);
+            let spp = store.intersect(spp, outputs);
+            if spp == store.zero {
+                continue;
+            }
+            let target = *number.entry(target).or_insert_with(|| {
+                order.push(target);
+                order.len() - 1
+            });
+            transitions.push((target, spp));
+        }
+        pruned.states.push(explicit.states[state]);
+        pruned.labels.push(explicit.labels[state].clone());
+        pruned
+            .transitions
+            .push(ST::new(transitions.into_iter().collect()));
+        pruned.epsilons.push(epsilon);
+        i += 1;
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aut::Aut;
+
+    #[test]
+    fn test_prune_empty() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "0",
+            "x0:=1; dup; x0==0",
+            "(x0:=1; dup)* ^ (x0:=1; dup)*",
+            "(x0:=1; dup)*; x0==0; dup; x0==1",
+        ] {
+            let state = aut.parse_state(input);
+            let pruned = aut.prune(state);
+            assert_eq!(pruned.num_states(), 0, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_prune_keeps_language() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "x0:=1; dup; x0==1",
+            "(x0:=1; dup)* + x1==1; dup; x1==0",
+            "(x0==0; x0:=1; dup)*; x1:=1",
+            "!(dup; dup) & X X (x1==1)",
+        ] {
+            let state = aut.parse_state(input);
+            let explicit = aut.explore(state);
+            let pruned = aut.prune(state);
+            assert!(pruned.num_states() <= explicit.num_states());
+            assert!(pruned.num_transitions() <= explicit.num_transitions());
+            // Pruning does not change the input/output relation
+            let store = aut.spp_store_mut();
+            let relation = crate::elim::eliminate(&explicit, store);
+            assert_eq!(
+                crate::elim::eliminate(&pruned, store),
+                relation,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_prune_restricts_spps() {
+        // After `x0:=1; dup`, only packets with x0 = 1 can reach the test
+        let mut aut = Aut::new(2);
+        let state = aut.parse_state("x0:=1; dup; (x0==0 + x0==1); x1:=1");
+        let pruned = aut.prune(state);
+        let expected = aut.parse_state("x0:=1; dup; x0==1; x1:=1");
+        let expected = aut.prune(expected);
+        assert_eq!(pruned.transitions, expected.transitions);
+        assert_eq!(pruned.epsilons, expected.epsilons);
+        assert!(pruned.epsilons[0] == aut.spp_store().zero);
+    }
+}
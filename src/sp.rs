@@ -1,15 +1,18 @@
 // Symbolic Packets represent sets of concrete packets.
-// They are represented in a BDD-like structure.
-// Unlike traditional BDDs, we do not leave out any levels of the BDD:
-// each path down the BDD has precisely the same depth, namely the number of variables, i.e. the packet size in bits.
+// They are represented as reduced ordered BDDs: each node tests one variable (one bit of the packet),
+// and the variables are tested in increasing order along every path.
+// Levels may be skipped: a variable that is not tested along a path is a don't-care,
+// so a node whose two children are equal is never created.
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::hash::Hash;
 
 /// We use indices into the SP store to represent SPs.
 /// The zero SP is represented by SP(0) and the one SP is represented by SP(1).
 /// Indices into the store are the u32 value - 2.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct SP(pub u32);
 
 impl SP {
@@ -34,10 +37,19 @@ impl fmt::Display for SP {
 
 type Var = u32;
 
+/// Rewrites the entries of a memo table after a garbage collection.
+/// `f` maps an entry to its remapped version, or to `None` if the entry mentions a removed node.
+pub fn remap_memo<K: Eq + Hash, V>(memo: &mut HashMap<K, V>, f: impl Fn(K, V) -> Option<(K, V)>) {
+    *memo = std::mem::take(memo)
+        .into_iter()
+        .filter_map(|(k, v)| f(k, v))
+        .collect();
+}
+
 /// The store of SPs.
 #[derive(Debug, Clone)]
 pub struct SPstore {
-    num_vars: Var, // Idea: it's ok to pick this larger than you need. Hash consing & memoization will handle it
+    num_vars: Var, // Idea: it's ok to pick this larger than you need. Unused variables are never tested
     // Note: 0 & 1 don't appear in `hc` or the arena `nodes`, they only appear in
     // the other memo tables
     nodes: Vec<SPnode>,
@@ -52,22 +64,36 @@ pub struct SPstore {
     ifelse_memo: HashMap<(Var, SP, SP), SP>,
 }
 
-/// A node in the SP store. Has two children, one for this variable being 0 and one for it being 1.
+/// A node in the SP store. Tests the variable `var` and has two children,
+/// one for this variable being 0 and one for it being 1.
+/// The children only test variables larger than `var`.
 /// An SPnode is a non-trivial SP (i.e. not zero and not one)
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
 pub struct SPnode {
+    pub var: Var,
     pub x0: SP,
     pub x1: SP,
 }
 
+/// A copy of some SPs that does not depend on a store, for saving them to disk.
+/// The nodes are numbered like in a store: `nodes[i]` is SP(i + 2), and its children `(var, x0, x1)`
+/// are zero, one or earlier nodes. `SPstore::export` numbers the nodes canonically,
+/// so equal SPs give equal dumps, whatever the history of the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SPdump {
+    pub num_vars: Var,
+    pub nodes: Vec<(Var, SP, SP)>,
+    pub roots: Vec<SP>,
+}
+
 impl SPstore {
     pub fn new(num_vars: Var) -> Self {
-        let mut store = Self {
+        Self {
             num_vars,
             nodes: vec![],
             hc: HashMap::new(),
             zero: SP::new(0),
-            one: SP::new(0), // Dummy values, will be set later
+            one: SP::new(1),
             // We prefill the memo tables with the results of the trivial cases
             // Need to benchmark if this is actually faster than checking these cases in the operations
             union_memo: HashMap::from([
@@ -84,18 +110,40 @@ impl SPstore {
             ]),
             complement_memo: HashMap::from([(SP::new(0), SP::new(1)), (SP::new(1), SP::new(0))]),
             ifelse_memo: HashMap::new(),
-        };
-        store.zero = store.zero();
-        store.one = store.one();
-        store
+        }
     }
 
     pub fn get(&self, sp: SP) -> SPnode {
         self.nodes[sp.as_usize() - 2]
     }
 
-    pub fn mk(&mut self, x0: SP, x1: SP) -> SP {
-        let node = SPnode { x0, x1 };
+    /// The variable tested by the root of `sp`, or `num_vars` if `sp` is zero or one
+    pub fn var(&self, sp: SP) -> Var {
+        if sp.as_u32() < 2 {
+            self.num_vars
+        } else {
+            self.get(sp).var
+        }
+    }
+
+    /// The children of `sp` for the variable `var`, which must not be larger than the variable of `sp`.
+    /// If `sp` skips `var`, the variable is a don't-care and both children are `sp` itself.
+    pub fn cofactors(&self, sp: SP, var: Var) -> (SP, SP) {
+        if self.var(sp) == var {
+            let node = self.get(sp);
+            (node.x0, node.x1)
+        } else {
+            (sp, sp)
+        }
+    }
+
+    pub fn mk(&mut self, var: Var, x0: SP, x1: SP) -> SP {
+        debug_assert!(var < self.var(x0) && var < self.var(x1));
+        // Redundant nodes are skipped
+        if x0 == x1 {
+            return x0;
+        }
+        let node = SPnode { var, x0, x1 };
 
         // Check if the node is already in the store using the hc table
         if let Some(sp) = self.hc.get(&node) {
@@ -109,29 +157,12 @@ impl SPstore {
         sp
     }
 
-    fn zero(&mut self) -> SP {
-        // We must construct a zero SP of the right depth
-        let mut sp = SP::new(0);
-        for _ in 0..self.num_vars {
-            sp = self.mk(sp, sp);
-        }
-        sp
-    }
-    fn one(&mut self) -> SP {
-        // We must construct a one SP of the right depth
-        let mut sp = SP::new(1);
-        for _ in 0..self.num_vars {
-            sp = self.mk(sp, sp);
-        }
-        sp
-    }
-
     /// Generates a random SP with `num_vars` variables
     pub fn rand(&mut self) -> SP {
         self.rand_helper(self.num_vars)
     }
 
-    /// Helper function for `rand`: generates a random SP with a certain `depth`
+    /// Helper function for `rand`: generates a random SP over the last `depth` variables
     fn rand_helper(&mut self, depth: Var) -> SP {
         if depth == 0 {
             return if rand::random::<bool>() {
@@ -142,7 +173,7 @@ impl SPstore {
         }
         let x0 = self.rand_helper(depth - 1);
         let x1 = self.rand_helper(depth - 1);
-        self.mk(x0, x1)
+        self.mk(self.num_vars - depth, x0, x1)
     }
 
     pub fn union(&mut self, a: SP, b: SP) -> SP {
@@ -150,12 +181,20 @@ impl SPstore {
         if let Some(&result) = self.union_memo.get(&(a, b)) {
             return result;
         }
-        // We now know that we've got a real node, so we don't need to handle 0 or 1 cases here
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x0 = self.union(a_node.x0, b_node.x0);
-        let x1 = self.union(a_node.x1, b_node.x1);
-        let res = self.mk(x0, x1);
+        let res = if a == self.zero || a == b {
+            b
+        } else if b == self.zero {
+            a
+        } else if a == self.one || b == self.one {
+            self.one
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a0, a1) = self.cofactors(a, var);
+            let (b0, b1) = self.cofactors(b, var);
+            let x0 = self.union(a0, b0);
+            let x1 = self.union(a1, b1);
+            self.mk(var, x0, x1)
+        };
         self.union_memo.insert((a, b), res);
         res
     }
@@ -165,13 +204,20 @@ impl SPstore {
         if let Some(&result) = self.intersect_memo.get(&(a, b)) {
             return result;
         }
-        // Because we prefilled the memo with base cases,
-        // we now know that we've got a real node, so we don't need to handle 0 or 1 cases here.
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x0 = self.intersect(a_node.x0, b_node.x0);
-        let x1 = self.intersect(a_node.x1, b_node.x1);
-        let res = self.mk(x0, x1);
+        let res = if a == self.one || a == b {
+            b
+        } else if b == self.one {
+            a
+        } else if a == self.zero || b == self.zero {
+            self.zero
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a0, a1) = self.cofactors(a, var);
+            let (b0, b1) = self.cofactors(b, var);
+            let x0 = self.intersect(a0, b0);
+            let x1 = self.intersect(a1, b1);
+            self.mk(var, x0, x1)
+        };
         self.intersect_memo.insert((a, b), res);
         res
     }
@@ -186,7 +232,7 @@ impl SPstore {
         let node = self.get(a);
         let x0 = self.complement(node.x0);
         let x1 = self.complement(node.x1);
-        let res = self.mk(x0, x1);
+        let res = self.mk(node.var, x0, x1);
         self.complement_memo.insert(a, res);
         res
     }
@@ -199,25 +245,26 @@ impl SPstore {
 
     pub fn ifelse(&mut self, var: Var, then_branch: SP, else_branch: SP) -> SP {
         assert!(var < self.num_vars);
-        self.ifelse_helper(var, then_branch, else_branch)
+        self.ifelse_helper(0, var, then_branch, else_branch)
     }
-    fn ifelse_helper(&mut self, var: Var, then_branch: SP, else_branch: SP) -> SP {
+    // Computes `ifelse` for the variables starting from `level`
+    fn ifelse_helper(&mut self, level: Var, var: Var, then_branch: SP, else_branch: SP) -> SP {
         // First, check the memo table
         if let Some(&result) = self.ifelse_memo.get(&(var, then_branch, else_branch)) {
             return result;
         }
-        let then_node = self.get(then_branch);
-        let else_node = self.get(else_branch);
+        let (then0, then1) = self.cofactors(then_branch, level);
+        let (else0, else1) = self.cofactors(else_branch, level);
         let x0;
         let x1;
-        if var == 0 {
-            x0 = then_node.x0;
-            x1 = else_node.x1;
+        if level == var {
+            x0 = then0;
+            x1 = else1;
         } else {
-            x0 = self.ifelse_helper(var - 1, then_node.x0, else_node.x0);
-            x1 = self.ifelse_helper(var - 1, then_node.x1, else_node.x1);
+            x0 = self.ifelse_helper(level + 1, var, then0, else0);
+            x1 = self.ifelse_helper(level + 1, var, then1, else1);
         }
-        let res = self.mk(x0, x1);
+        let res = self.mk(level, x0, x1);
         self.ifelse_memo
             .insert((var, then_branch, else_branch), res);
         res
@@ -232,12 +279,180 @@ impl SPstore {
         }
     }
 
+    /// Returns the SP containing exactly the given concrete packet
+    pub fn singleton(&mut self, packet: &[bool]) -> SP {
+        assert_eq!(packet.len(), self.num_vars as usize);
+        let mut sp = self.one;
+        for (var, &value) in packet.iter().enumerate().rev() {
+            sp = if value {
+                self.mk(var as Var, self.zero, sp)
+            } else {
+                self.mk(var as Var, sp, self.zero)
+            };
+        }
+        sp
+    }
+
+    /// Picks the lexicographically smallest concrete packet in `sp`,
+    /// preferring 0 over 1 for x0, then x1, and so on.
+    /// Returns `None` if `sp` is empty.
+    pub fn pick(&self, sp: SP) -> Option<Vec<bool>> {
+        if sp == self.zero {
+            return None;
+        }
+        // Every SP other than zero is non-empty, so we can pick greedily
+        let mut sp = sp;
+        let mut packet = vec![];
+        for var in 0..self.num_vars {
+            let (x0, x1) = self.cofactors(sp, var);
+            if x0 != self.zero {
+                packet.push(false);
+                sp = x0;
+            } else {
+                packet.push(true);
+                sp = x1;
+            }
+        }
+        Some(packet)
+    }
+
+    /// Checks whether the concrete packet is contained in `sp`
+    pub fn contains(&self, sp: SP, packet: &[bool]) -> bool {
+        assert_eq!(packet.len(), self.num_vars as usize);
+        let mut sp = sp;
+        for (var, &value) in packet.iter().enumerate() {
+            let (x0, x1) = self.cofactors(sp, var as Var);
+            sp = if value { x1 } else { x0 };
+        }
+        sp == self.one
+    }
+
+    /// The number of nodes in the arena
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Mark-and-sweep garbage collection.
+    /// Removes all nodes that are not reachable from `roots`,
+    /// compacts the arena and purges the memo entries that mention removed nodes.
+    /// Returns the new SP of each surviving SP; all other SPs are invalidated.
+    pub fn gc(&mut self, roots: &[SP]) -> HashMap<SP, SP> {
+        // Mark
+        let mut live = vec![false; self.nodes.len()];
+        let mut stack: Vec<SP> = roots.to_vec();
+        while let Some(sp) = stack.pop() {
+            if sp.as_u32() < 2 || live[sp.as_usize() - 2] {
+                continue;
+            }
+            live[sp.as_usize() - 2] = true;
+            let SPnode { x0, x1, .. } = self.get(sp);
+            stack.push(x0);
+            stack.push(x1);
+        }
+
+        // Sweep. Children are always created before their parents,
+        // so their new SPs are known by the time we reach the parent.
+        let mut remap = HashMap::from([(SP::new(0), SP::new(0)), (SP::new(1), SP::new(1))]);
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.hc.clear();
+        for (i, node) in old_nodes.into_iter().enumerate() {
+            if !live[i] {
+                continue;
+            }
+            let node = SPnode {
+                var: node.var,
+                x0: remap[&node.x0],
+                x1: remap[&node.x1],
+            };
+            let sp = SP::new(self.nodes.len() as u32 + 2);
+            self.nodes.push(node);
+            self.hc.insert(node, sp);
+            remap.insert(SP::new(i as u32 + 2), sp);
+        }
+
+        // Purge the memo tables, keeping the entries whose SPs all survived
+        let r = |sp: SP| remap.get(&sp).copied();
+        remap_memo(&mut self.union_memo, |(a, b), res| Some(((r(a)?, r(b)?), r(res)?)));
+        remap_memo(&mut self.intersect_memo, |(a, b), res| {
+            Some(((r(a)?, r(b)?), r(res)?))
+        });
+        remap_memo(&mut self.complement_memo, |a, res| Some((r(a)?, r(res)?)));
+        remap_memo(&mut self.ifelse_memo, |(var, a, b), res| {
+            Some(((var, r(a)?, r(b)?), r(res)?))
+        });
+        remap
+    }
+
+    /// Copies the SPs `roots` and their nodes into a dump.
+    /// The nodes are numbered in the order in which a depth-first search from the roots
+    /// (in order, and `x0` before `x1`) finishes them.
+    pub fn export(&self, roots: &[SP]) -> SPdump {
+        let mut index = HashMap::from([(self.zero, self.zero), (self.one, self.one)]);
+        let mut nodes = vec![];
+        let roots = roots
+            .iter()
+            .map(|&sp| self.export_helper(sp, &mut index, &mut nodes))
+            .collect();
+        SPdump { num_vars: self.num_vars, nodes, roots }
+    }
+
+    fn export_helper(&self, sp: SP, index: &mut HashMap<SP, SP>, nodes: &mut Vec<(Var, SP, SP)>) -> SP {
+        if let Some(&i) = index.get(&sp) {
+            return i;
+        }
+        let SPnode { var, x0, x1 } = self.get(sp);
+        let x0 = self.export_helper(x0, index, nodes);
+        let x1 = self.export_helper(x1, index, nodes);
+        let i = SP::new(nodes.len() as u32 + 2);
+        nodes.push((var, x0, x1));
+        index.insert(sp, i);
+        i
+    }
+
+    /// Adds the nodes of a dump to this store, hash-consing them with the existing nodes,
+    /// and returns the SPs of its roots. Fails if the dump needs more variables than the store has,
+    /// or if it is not a valid table of ordered nodes.
+    pub fn load(&mut self, dump: &SPdump) -> Result<Vec<SP>, String> {
+        if dump.num_vars > self.num_vars {
+            return Err(format!(
+                "The dump has {} variables, but the store only has {}",
+                dump.num_vars, self.num_vars
+            ));
+        }
+        // The SP of each node of the dump in this store, and its variable in the dump
+        let mut sps = vec![self.zero, self.one];
+        let mut vars = vec![dump.num_vars, dump.num_vars];
+        for (i, &(var, x0, x1)) in dump.nodes.iter().enumerate() {
+            if var >= dump.num_vars {
+                return Err(format!("Node {} tests x{}, but the dump has {} variables", i + 2, var, dump.num_vars));
+            }
+            for x in [x0, x1] {
+                if x.as_usize() >= sps.len() {
+                    return Err(format!("Node {} refers to the later node {}", i + 2, x.as_u32()));
+                }
+                if var >= vars[x.as_usize()] {
+                    return Err(format!("Node {} is out of order on x{}", i + 2, var));
+                }
+            }
+            sps.push(self.mk(var, sps[x0.as_usize()], sps[x1.as_usize()]));
+            vars.push(var);
+        }
+        dump.roots
+            .iter()
+            .map(|root| {
+                sps.get(root.as_usize())
+                    .copied()
+                    .ok_or_else(|| format!("Root {} does not exist", root.as_u32()))
+            })
+            .collect()
+    }
+
     /// Enumerates all possible SPs with `num_vars` fields
     pub fn all(&mut self) -> Vec<SP> {
         return self.all_helper(self.num_vars);
     }
 
-    /// Helper function for `all`: enumerates all SPs with `depth` fields
+    /// Helper function for `all`: enumerates all SPs over the last `depth` fields
     pub fn all_helper(&mut self, depth: Var) -> Vec<SP> {
         if depth == 0 {
             return vec![SP::new(0), SP::new(1)];
@@ -246,7 +461,7 @@ impl SPstore {
         let mut result = vec![];
         for &x0 in &all_rec {
             for &x1 in &all_rec {
-                result.push(self.mk(x0, x1))
+                result.push(self.mk(self.num_vars - depth, x0, x1))
             }
         }
         result
@@ -268,6 +483,60 @@ mod tests {
 
     const N: Var = 2;
 
+    /// Tests that a dump reloads to the same SPs and does not depend on the history of the store
+    #[test]
+    fn test_dump() {
+        let mut s = SPstore::new(3);
+        let roots = s.some();
+        let dump = s.export(&roots);
+        let json = serde_json::to_string(&dump).unwrap();
+        let dump: SPdump = serde_json::from_str(&json).unwrap();
+        // Loading into the same store finds the existing nodes
+        assert_eq!(s.load(&dump), Ok(roots.clone()));
+        // A fresh store, with garbage and more variables, gets the same dump back
+        let mut t = SPstore::new(4);
+        t.some();
+        let loaded = t.load(&dump).unwrap();
+        assert_eq!(t.export(&loaded), SPdump { num_vars: 4, ..dump.clone() });
+        assert!(SPstore::new(2).load(&dump).is_err());
+        let unordered = SPdump { num_vars: 3, nodes: vec![(1, SP(0), SP(1)), (1, SP(2), SP(1))], roots: vec![SP(3)] };
+        assert_eq!(s.load(&unordered), Err("Node 3 is out of order on x1".to_string()));
+    }
+
+    /// Tests that garbage collection preserves the roots and removes everything else
+    #[test]
+    fn test_gc() {
+        let mut s = SPstore::new(3);
+        let packets: Vec<Vec<bool>> = (0..8)
+            .map(|i: u32| (0..3).map(|j| (i >> (2 - j)) & 1 == 1).collect())
+            .collect();
+        let root = s.rand();
+        let expected: Vec<bool> = packets.iter().map(|p| s.contains(root, p)).collect();
+        // Create some garbage
+        for p in &packets {
+            let singleton = s.singleton(p);
+            s.complement(singleton);
+        }
+        let num_nodes = s.num_nodes();
+        let remap = s.gc(&[root]);
+        assert!(s.num_nodes() < num_nodes);
+        let root = remap[&root];
+        let actual: Vec<bool> = packets.iter().map(|p| s.contains(root, p)).collect();
+        assert_eq!(actual, expected);
+        // Hash consing and the operations still work after compaction
+        let mut rebuilt = s.zero;
+        for (p, &member) in packets.iter().zip(&expected) {
+            if member {
+                let singleton = s.singleton(p);
+                rebuilt = s.union(rebuilt, singleton);
+            }
+        }
+        assert_eq!(rebuilt, root);
+        let complement = s.complement(root);
+        assert_eq!(s.complement(complement), root);
+        assert_eq!(s.complement(s.one), s.zero);
+    }
+
     #[test]
     fn test_laws_0() {
         let mut s = SPstore::new(N);
@@ -1,16 +1,18 @@
 // Symbolic Packet Programs represent relations of concrete packets.
-// They are represented in a BDD-like structure.
-// Unlike traditional BDDs, we do not leave out any levels of the BDD:
-// each path down the BDD has precisely the same depth, namely the number of variables, i.e. the packet size in bits.
-
-use crate::sp::{SPnode, SPstore, SP};
-#[allow(non_snake_case)]
+// They are represented in a BDD-like structure: each node looks at one variable (one bit of the packet)
+// of both the input and the output packet, and the variables are visited in increasing order along every path.
+// Levels may be skipped: a variable that is skipped along a path is left unchanged,
+// i.e. the input and the output packet agree on it. The one SPP (the identity) therefore needs no nodes,
+// and a node of the form (x, 0, 0, x) is never created.
+
+use crate::sp::{remap_memo, SPdump, SPstore, SP};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// We use indices into the SPP store to represent SPPs.
 /// The zero SPP is represented by SPP(0) and the one SPP is represented by SPP(1).
 /// Indices into the store are the u32 value - 2.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct SPP(pub u32);
 
 impl SPP {
@@ -38,7 +40,7 @@ pub type Var = u32;
 /// The store of SPPs. (store = arena + memo tables)
 #[derive(Debug)]
 pub struct SPPstore {
-    num_vars: Var, // Idea: it's ok to pick this larger than you need. Hash consing & memoization will handle it
+    num_vars: Var, // Idea: it's ok to pick this larger than you need. Unused variables are skipped
     nodes: Vec<SPPnode>, // the arena
     hc: HashMap<SPPnode, SPP>,
     pub zero: SPP,
@@ -52,26 +54,39 @@ pub struct SPPstore {
     difference_memo: HashMap<(SPP, SPP), SPP>,
     sequence_memo: HashMap<(SPP, SPP), SPP>,
     star_memo: HashMap<SPP, SPP>,
-    complement_memo: HashMap<SPP, SPP>,
-    // branch_memo: HashMap<(Var, SPP, SPP, SPP, SPP), SPP>,
-    test_memo: HashMap<(Var, bool), SPP>,
-    assign_memo: HashMap<(Var, bool), SPP>,
+    // The complement depends on the level: the complement of a skipped level is not the identity
+    complement_memo: HashMap<(Var, SPP), SPP>,
     flip_memo: HashMap<SPP, SPP>,
 
     pub sp: SPstore,
     fwd_memo: HashMap<SPP, SP>,
-    ifwd_memo: HashMap<SP, SPP>,
+    // Like the complement, `ifwd` depends on the level: a skipped level of an SP is a don't-care
+    ifwd_memo: HashMap<(Var, SP), SPP>,
 }
 
-/// A node in the SPP store. Has four children, one for each combination of the two variables.
+/// A node in the SPP store. Looks at the variable `var` and has four children,
+/// one for each combination of the input and output value of the variable.
+/// The children only look at variables larger than `var`.
 #[derive(Debug, Eq, Hash, PartialEq, Clone, Copy)]
 pub struct SPPnode {
+    pub var: Var,
     pub x00: SPP,
     pub x01: SPP,
     pub x10: SPP,
     pub x11: SPP,
 }
 
+/// A copy of some SPPs and SPs that does not depend on a store, for saving them to disk
+/// (see `SPdump`). The nodes `(var, x00, x01, x10, x11)` are numbered like in a store,
+/// and canonically by `SPPstore::export`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SPPdump {
+    pub num_vars: Var,
+    pub nodes: Vec<(Var, SPP, SPP, SPP, SPP)>,
+    pub roots: Vec<SPP>,
+    pub sp: SPdump,
+}
+
 impl SPPstore {
     pub fn new(num_vars: Var) -> Self {
         let mut store = Self {
@@ -79,8 +94,8 @@ impl SPPstore {
             nodes: vec![],
             hc: HashMap::new(),
             zero: SPP::new(0),
-            one: SPP::new(0),
-            top: SPP::new(0), // Dummy values, will be set later
+            one: SPP::new(1),
+            top: SPP::new(0), // Dummy value, will be set later
             // We prefill the memo tables with the results of the trivial cases
             // Need to benchmark if this is actually faster than checking these cases in the operations
             union_memo: HashMap::from([
@@ -114,22 +129,21 @@ impl SPPstore {
                 ((SPP::new(1), SPP::new(1)), SPP::new(1)),
             ]),
             star_memo: HashMap::from([(SPP::new(0), SPP::new(1)), (SPP::new(1), SPP::new(1))]),
+            // Below the last variable, 0 and 1 are each other's complement
             complement_memo: HashMap::from([
-                (SPP::new(0), SPP::new(1)),
-                (SPP::new(1), SPP::new(0)),
+                ((num_vars, SPP::new(0)), SPP::new(1)),
+                ((num_vars, SPP::new(1)), SPP::new(0)),
             ]),
-            // branch_memo: HashMap::new(),
-            test_memo: HashMap::new(),
-            assign_memo: HashMap::new(),
             flip_memo: HashMap::from([(SPP::new(0), SPP::new(0)), (SPP::new(1), SPP::new(1))]),
             sp: SPstore::new(num_vars),
 
             // in the memo tables, we only want the base cases for 0 and 1
             fwd_memo: HashMap::from([(SPP::new(0), SP::new(0)), (SPP::new(1), SP::new(1))]),
-            ifwd_memo: HashMap::from([(SP::new(0), SPP::new(0)), (SP::new(1), SPP::new(1))]),
+            ifwd_memo: HashMap::from([
+                ((num_vars, SP::new(0)), SPP::new(0)),
+                ((num_vars, SP::new(1)), SPP::new(1)),
+            ]),
         };
-        store.zero = store.zero();
-        store.one = store.one();
         store.top = store.top();
 
         store
@@ -151,6 +165,27 @@ impl SPPstore {
         self.nodes[node_index]
     }
 
+    /// The variable looked at by the root of `spp`, or `num_vars` if `spp` is zero or one
+    pub fn var(&self, spp: SPP) -> Var {
+        if spp.as_u32() < 2 {
+            self.num_vars
+        } else {
+            self.get(spp).var
+        }
+    }
+
+    /// The children `(x00, x01, x10, x11)` of `spp` for the variable `var`,
+    /// which must not be larger than the variable of `spp`.
+    /// If `spp` skips `var`, the variable is left unchanged, so only `x00` and `x11` are non-zero.
+    pub fn cofactors(&self, spp: SPP, var: Var) -> (SPP, SPP, SPP, SPP) {
+        if self.var(spp) == var {
+            let SPPnode { x00, x01, x10, x11, .. } = self.get(spp);
+            (x00, x01, x10, x11)
+        } else {
+            (spp, self.zero, self.zero, spp)
+        }
+    }
+
     /// Computes the possible output packet set from applying the `SPP`
     pub fn fwd(&mut self, spp: SPP) -> SP {
         // Check the memo table to see if fwd(spp) already exists
@@ -159,9 +194,11 @@ impl SPPstore {
         }
 
         // We now know that we've got a non-trivial SPPNode,
-        // so we don't need to handle 0 or 1 cases here
+        // so we don't need to handle 0 or 1 cases here.
+        // A skipped level leaves an arbitrary input unchanged,
+        // which is a don't-care in the output.
 
-        let SPPnode { x00, x01, x10, x11 } = self.get(spp);
+        let SPPnode { var, x00, x01, x10, x11 } = self.get(spp);
 
         let f00 = self.fwd(x00);
         let f10 = self.fwd(x10);
@@ -169,7 +206,9 @@ impl SPPstore {
         let f11 = self.fwd(x11);
         let x0 = self.sp.union(f00, f10);
         let x1 = self.sp.union(f01, f11);
-        self.sp.mk(x0, x1)
+        let res = self.sp.mk(var, x0, x1);
+        self.fwd_memo.insert(spp, res);
+        res
     }
 
     /// Computes the set of packets, which when input to the `spp`,
@@ -182,17 +221,27 @@ impl SPPstore {
     /// Computes the SPP corresponding to the `sp` returned by `fwd`.     
     /// - `ifwd` is the right inverse of `fwd`, i.e. `fwd ∘ ifwd = id_SP`
     pub fn ifwd(&mut self, sp: SP) -> SPP {
+        self.ifwd_helper(0, sp)
+    }
+
+    /// Computes `ifwd` for the variables starting from `level`
+    fn ifwd_helper(&mut self, level: Var, sp: SP) -> SPP {
+        if sp == self.sp.zero {
+            return self.zero;
+        }
         // Check the memo table to see if ifwd(spp) already exists
-        if let Some(&result) = self.ifwd_memo.get(&sp) {
+        if let Some(&result) = self.ifwd_memo.get(&(level, sp)) {
             return result;
         }
 
-        let SPnode { x0, x1 } = self.sp.get(sp);
-        let x00 = self.ifwd(x0);
-        let x01 = self.ifwd(x1);
+        let (x0, x1) = self.sp.cofactors(sp, level);
+        let x00 = self.ifwd_helper(level + 1, x0);
+        let x01 = self.ifwd_helper(level + 1, x1);
         let x10 = x00;
         let x11 = x01;
-        self.mk(x00, x01, x10, x11)
+        let res = self.mk(level, x00, x01, x10, x11);
+        self.ifwd_memo.insert((level, sp), res);
+        res
     }
 
     /// Computes the SPP corresponding to the `sp` returned by `bwd`.            
@@ -202,8 +251,19 @@ impl SPPstore {
         self.flip(spp)
     }
 
-    fn mk(&mut self, x00: SPP, x01: SPP, x10: SPP, x11: SPP) -> SPP {
-        let node = SPPnode { x00, x01, x10, x11 };
+    fn mk(&mut self, var: Var, x00: SPP, x01: SPP, x10: SPP, x11: SPP) -> SPP {
+        debug_assert!([x00, x01, x10, x11].iter().all(|&x| var < self.var(x)));
+        // Nodes that leave the variable unchanged are skipped
+        if x01 == self.zero && x10 == self.zero && x00 == x11 {
+            return x00;
+        }
+        let node = SPPnode {
+            var,
+            x00,
+            x01,
+            x10,
+            x11,
+        };
 
         // Check if the node is already in the store using the hc table
         if let Some(spp) = self.hc.get(&node) {
@@ -217,31 +277,18 @@ impl SPPstore {
         spp
     }
 
-    fn zero(&mut self) -> SPP {
-        // We must construct a zero SPP of the right depth
-        let mut spp = SPP::new(0);
-        for _ in 0..self.num_vars {
-            spp = self.mk(spp, spp, spp, spp);
-        }
-        spp
-    }
     fn top(&mut self) -> SPP {
-        // We must construct a top SPP of the right depth
-        let mut spp = SPP::new(1);
-        for _ in 0..self.num_vars {
-            spp = self.mk(spp, spp, spp, spp);
-        }
-        spp
+        self.top_helper(0)
     }
-    fn one(&mut self) -> SPP {
-        // We must construct a one SPP of the right depth
-        let mut spp_one = SPP::new(1);
-        let mut spp_zero = SPP::new(0);
-        for _ in 0..self.num_vars {
-            spp_one = self.mk(spp_one, spp_zero, spp_zero, spp_one);
-            spp_zero = self.mk(spp_zero, spp_zero, spp_zero, spp_zero);
+
+    /// The top SPP for the variables starting from `level`.
+    /// Unlike the identity, top does not skip any levels.
+    fn top_helper(&mut self, level: Var) -> SPP {
+        let mut spp = self.one;
+        for var in (level..self.num_vars).rev() {
+            spp = self.mk(var, spp, spp, spp, spp);
         }
-        spp_one
+        spp
     }
 
     pub fn union(&mut self, a: SPP, b: SPP) -> SPP {
@@ -249,14 +296,20 @@ impl SPPstore {
         if let Some(&result) = self.union_memo.get(&(a, b)) {
             return result;
         }
-        // We now know that we've got a real node, so we don't need to handle 0 or 1 cases here
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x00 = self.union(a_node.x00, b_node.x00);
-        let x01 = self.union(a_node.x01, b_node.x01);
-        let x10 = self.union(a_node.x10, b_node.x10);
-        let x11 = self.union(a_node.x11, b_node.x11);
-        let res = self.mk(x00, x01, x10, x11);
+        let res = if a == self.zero || a == b {
+            b
+        } else if b == self.zero {
+            a
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a00, a01, a10, a11) = self.cofactors(a, var);
+            let (b00, b01, b10, b11) = self.cofactors(b, var);
+            let x00 = self.union(a00, b00);
+            let x01 = self.union(a01, b01);
+            let x10 = self.union(a10, b10);
+            let x11 = self.union(a11, b11);
+            self.mk(var, x00, x01, x10, x11)
+        };
         self.union_memo.insert((a, b), res);
         res
     }
@@ -266,15 +319,20 @@ impl SPPstore {
         if let Some(&result) = self.intersect_memo.get(&(a, b)) {
             return result;
         }
-        // Because we prefilled the memo with base cases,
-        // we now know that we've got a real node, so we don't need to handle 0 or 1 cases here.
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x00 = self.intersect(a_node.x00, b_node.x00);
-        let x01 = self.intersect(a_node.x01, b_node.x01);
-        let x10 = self.intersect(a_node.x10, b_node.x10);
-        let x11 = self.intersect(a_node.x11, b_node.x11);
-        let res = self.mk(x00, x01, x10, x11);
+        let res = if a == self.zero || b == self.zero {
+            self.zero
+        } else if a == b {
+            a
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a00, a01, a10, a11) = self.cofactors(a, var);
+            let (b00, b01, b10, b11) = self.cofactors(b, var);
+            let x00 = self.intersect(a00, b00);
+            let x01 = self.intersect(a01, b01);
+            let x10 = self.intersect(a10, b10);
+            let x11 = self.intersect(a11, b11);
+            self.mk(var, x00, x01, x10, x11)
+        };
         self.intersect_memo.insert((a, b), res);
         res
     }
@@ -284,15 +342,22 @@ impl SPPstore {
         if let Some(&result) = self.xor_memo.get(&(a, b)) {
             return result;
         }
-        // Because we prefilled the memo with base cases,
-        // we now know that we've got a real node, so we don't need to handle 0 or 1 cases here.
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x00 = self.xor(a_node.x00, b_node.x00);
-        let x01 = self.xor(a_node.x01, b_node.x01);
-        let x10 = self.xor(a_node.x10, b_node.x10);
-        let x11 = self.xor(a_node.x11, b_node.x11);
-        let res = self.mk(x00, x01, x10, x11);
+        let res = if a == self.zero {
+            b
+        } else if b == self.zero {
+            a
+        } else if a == b {
+            self.zero
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a00, a01, a10, a11) = self.cofactors(a, var);
+            let (b00, b01, b10, b11) = self.cofactors(b, var);
+            let x00 = self.xor(a00, b00);
+            let x01 = self.xor(a01, b01);
+            let x10 = self.xor(a10, b10);
+            let x11 = self.xor(a11, b11);
+            self.mk(var, x00, x01, x10, x11)
+        };
         self.xor_memo.insert((a, b), res);
         res
     }
@@ -302,35 +367,46 @@ impl SPPstore {
         if let Some(&result) = self.difference_memo.get(&(a, b)) {
             return result;
         }
-        // Because we prefilled the memo with base cases,
-        // we now know that we've got a real node, so we don't need to handle 0 or 1 cases here.
         // Difference a - b is defined as a & !b.
         // We could implement it that way, but recursive definition is simpler here.
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        let x00 = self.difference(a_node.x00, b_node.x00);
-        let x01 = self.difference(a_node.x01, b_node.x01);
-        let x10 = self.difference(a_node.x10, b_node.x10);
-        let x11 = self.difference(a_node.x11, b_node.x11);
-        let res = self.mk(x00, x01, x10, x11);
+        let res = if a == self.zero || a == b {
+            self.zero
+        } else if b == self.zero {
+            a
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a00, a01, a10, a11) = self.cofactors(a, var);
+            let (b00, b01, b10, b11) = self.cofactors(b, var);
+            let x00 = self.difference(a00, b00);
+            let x01 = self.difference(a01, b01);
+            let x10 = self.difference(a10, b10);
+            let x11 = self.difference(a11, b11);
+            self.mk(var, x00, x01, x10, x11)
+        };
         self.difference_memo.insert((a, b), res); // Insert result into memo table
         res
     }
 
     pub fn complement(&mut self, a: SPP) -> SPP {
+        self.complement_helper(0, a)
+    }
+
+    /// Computes the complement for the variables starting from `level`
+    fn complement_helper(&mut self, level: Var, a: SPP) -> SPP {
         // First, check the memo table
-        if let Some(&result) = self.complement_memo.get(&a) {
+        if let Some(&result) = self.complement_memo.get(&(level, a)) {
             return result;
         }
-        // Because we prefilled the memo with base cases,
-        // we now know that we've got a real node, so we don't need to handle 0 or 1 cases here.
-        let node = self.get(a);
-        let x00 = self.complement(node.x00);
-        let x01 = self.complement(node.x01);
-        let x10 = self.complement(node.x10);
-        let x11 = self.complement(node.x11);
-        let res = self.mk(x00, x01, x10, x11);
-        self.complement_memo.insert(a, res);
+        // Because we prefilled the memo with the base cases below the last variable,
+        // we now know that `level` is an actual variable.
+        // We cannot skip it, even if `a` does: `a` leaves it unchanged but its complement need not.
+        let (a00, a01, a10, a11) = self.cofactors(a, level);
+        let x00 = self.complement_helper(level + 1, a00);
+        let x01 = self.complement_helper(level + 1, a01);
+        let x10 = self.complement_helper(level + 1, a10);
+        let x11 = self.complement_helper(level + 1, a11);
+        let res = self.mk(level, x00, x01, x10, x11);
+        self.complement_memo.insert((level, a), res);
         res
     }
 
@@ -339,30 +415,38 @@ impl SPPstore {
         if let Some(&result) = self.sequence_memo.get(&(a, b)) {
             return result;
         }
-        // We now know that we've got a real node, so we don't need to handle 0 or 1 cases here
-        let a_node = self.get(a);
-        let b_node = self.get(b);
-        // This is like matrix multiplication
-        // (a00, a01; a10, a11) * (b00, b01; b10, b11) = (a00*b00 + a01*b10, a00*b01 + a01*b11; a10*b00 + a11*b10, a10*b01 + a11*b11)
-        // Pictorially:
-        //                      b00 b01
-        //                      b10 b11
-        //
-        // a00 a01      a00b00 + a01b10  a00b01 + a01b11
-        // a10 a11      a10b00 + a11b10  a10b01 + a11b11
-        let a00b00 = self.sequence(a_node.x00, b_node.x00);
-        let a01b10 = self.sequence(a_node.x01, b_node.x10);
-        let a00b01 = self.sequence(a_node.x00, b_node.x01);
-        let a01b11 = self.sequence(a_node.x01, b_node.x11);
-        let a10b00 = self.sequence(a_node.x10, b_node.x00);
-        let a11b10 = self.sequence(a_node.x11, b_node.x10);
-        let a10b01 = self.sequence(a_node.x10, b_node.x01);
-        let a11b11 = self.sequence(a_node.x11, b_node.x11);
-        let x00 = self.union(a00b00, a01b10);
-        let x01 = self.union(a00b01, a01b11);
-        let x10 = self.union(a10b00, a11b10);
-        let x11 = self.union(a10b01, a11b11);
-        let res = self.mk(x00, x01, x10, x11);
+        let res = if a == self.zero || b == self.zero {
+            self.zero
+        } else if a == self.one {
+            b
+        } else if b == self.one {
+            a
+        } else {
+            let var = self.var(a).min(self.var(b));
+            let (a00, a01, a10, a11) = self.cofactors(a, var);
+            let (b00, b01, b10, b11) = self.cofactors(b, var);
+            // This is like matrix multiplication
+            // (a00, a01; a10, a11) * (b00, b01; b10, b11) = (a00*b00 + a01*b10, a00*b01 + a01*b11; a10*b00 + a11*b10, a10*b01 + a11*b11)
+            // Pictorially:
+            //                      b00 b01
+            //                      b10 b11
+            //
+            // a00 a01      a00b00 + a01b10  a00b01 + a01b11
+            // a10 a11      a10b00 + a11b10  a10b01 + a11b11
+            let a00b00 = self.sequence(a00, b00);
+            let a01b10 = self.sequence(a01, b10);
+            let a00b01 = self.sequence(a00, b01);
+            let a01b11 = self.sequence(a01, b11);
+            let a10b00 = self.sequence(a10, b00);
+            let a11b10 = self.sequence(a11, b10);
+            let a10b01 = self.sequence(a10, b01);
+            let a11b11 = self.sequence(a11, b11);
+            let x00 = self.union(a00b00, a01b10);
+            let x01 = self.union(a00b01, a01b11);
+            let x10 = self.union(a10b00, a11b10);
+            let x11 = self.union(a10b01, a11b11);
+            self.mk(var, x00, x01, x10, x11)
+        };
         self.sequence_memo.insert((a, b), res);
         res
     }
@@ -377,6 +461,17 @@ impl SPPstore {
         self.fwd(seq_ifwd_sp_spp)
     }
 
+    /// Picks a packet pair of `spp`: the smallest input packet that has an output (see `SPstore::pick`),
+    /// and its smallest output packet. Returns `None` if `spp` is empty.
+    pub fn pick(&mut self, spp: SPP) -> Option<(Vec<bool>, Vec<bool>)> {
+        let inputs = self.bwd(spp);
+        let input = self.sp.pick(inputs)?;
+        let singleton = self.sp.singleton(&input);
+        let outputs = self.push(singleton, spp);
+        let output = self.sp.pick(outputs)?;
+        Some((input, output))
+    }
+
     /// A concrete packet `α ∈ pull(spp, sp)` iff running `spp` on `α`
     /// produces an output packet in the `sp`.   
     /// In other words, `pull` simulates the backward transition of an SP
@@ -411,52 +506,215 @@ impl SPPstore {
         let res_c = d_star_c_res_a;
         let res_c_bd_star = self.sequence(res_c, bd_star);
         let res_d = self.union(d_star, res_c_bd_star);
-        let res = self.mk(res_a, res_b, res_c, res_d);
+        let res = self.mk(x_node.var, res_a, res_b, res_c, res_d);
         self.star_memo.insert(x, res);
         res
     }
 
     pub fn test(&mut self, var: Var, value: bool) -> SPP {
-        if let Some(&result) = self.test_memo.get(&(var, value)) {
+        assert!(var < self.num_vars);
+        // All other variables are skipped
+        if value {
+            self.mk(var, self.zero, self.zero, self.zero, self.one)
+        } else {
+            self.mk(var, self.one, self.zero, self.zero, self.zero)
+        }
+    }
+
+    pub fn assign(&mut self, var: Var, value: bool) -> SPP {
+        assert!(var < self.num_vars);
+        // All other variables are skipped
+        if value {
+            self.mk(var, self.zero, self.one, self.zero, self.one)
+        } else {
+            self.mk(var, self.one, self.zero, self.one, self.zero)
+        }
+    }
+
+    /// Checks whether the pair of concrete packets `(input, output)` is in the relation `spp`
+    pub fn contains(&self, spp: SPP, input: &[bool], output: &[bool]) -> bool {
+        assert_eq!(input.len(), self.num_vars as usize);
+        assert_eq!(output.len(), self.num_vars as usize);
+        let mut spp = spp;
+        for (var, (&i, &o)) in input.iter().zip(output).enumerate() {
+            let (x00, x01, x10, x11) = self.cofactors(spp, var as Var);
+            spp = match (i, o) {
+                (false, false) => x00,
+                (false, true) => x01,
+                (true, false) => x10,
+                (true, true) => x11,
+            };
+        }
+        spp == self.one
+    }
+
+    /// Existentially quantifies the variables `hidden` (sorted) on both the input and the output side:
+    /// a pair is in the result iff it agrees with a pair of `spp` on all other variables
+    pub fn exists(&mut self, spp: SPP, hidden: &[Var]) -> SPP {
+        self.exists_helper(0, spp, hidden, &mut HashMap::new())
+    }
+
+    fn exists_helper(
+        &mut self,
+        level: Var,
+        spp: SPP,
+        hidden: &[Var],
+        memo: &mut HashMap<(Var, SPP), SPP>,
+    ) -> SPP {
+        // Below the last hidden variable nothing changes
+        if spp == self.zero || hidden.last().is_none_or(|&last| last < level) {
+            return spp;
+        }
+        if let Some(&result) = memo.get(&(level, spp)) {
             return result;
         }
-        let mut res = SPP::new(1);
-        let mut zero = SPP::new(0);
-        for i in (0..self.num_vars).rev() {
-            if i == var {
-                if value {
-                    res = self.mk(zero, zero, zero, res);
-                } else {
-                    res = self.mk(res, zero, zero, zero);
+        let (x00, x01, x10, x11) = self.cofactors(spp, level);
+        let res = if hidden.contains(&level) {
+            let x0 = self.union(x00, x01);
+            let x1 = self.union(x10, x11);
+            let x = self.union(x0, x1);
+            let x = self.exists_helper(level + 1, x, hidden, memo);
+            self.mk(level, x, x, x, x)
+        } else {
+            let x00 = self.exists_helper(level + 1, x00, hidden, memo);
+            let x01 = self.exists_helper(level + 1, x01, hidden, memo);
+            let x10 = self.exists_helper(level + 1, x10, hidden, memo);
+            let x11 = self.exists_helper(level + 1, x11, hidden, memo);
+            self.mk(level, x00, x01, x10, x11)
+        };
+        memo.insert((level, spp), res);
+        res
+    }
+
+    /// Splits `spp` by the values that the `hidden` variables (sorted) of the output can take.
+    /// The hidden variables of the input are quantified away.
+    ///
+    /// Returns pairs `(guard, outputs)`, where the guards are disjoint and leave the hidden
+    /// variables arbitrary, and `outputs` is an SP over the hidden variables only.
+    /// A pair `(p, q)` is in `guard` iff the hidden parts of the outputs of `spp` for the inputs
+    /// that agree with `p`, restricted to the outputs that agree with `q`, are exactly `outputs`.
+    /// Pairs for which there are no such outputs are in no guard.
+    pub fn split_hidden(&mut self, spp: SPP, hidden: &[Var]) -> Vec<(SPP, SP)> {
+        self.split_hidden_helper(0, spp, hidden, &mut HashMap::new())
+    }
+
+    fn split_hidden_helper(
+        &mut self,
+        level: Var,
+        spp: SPP,
+        hidden: &[Var],
+        memo: &mut HashMap<(Var, SPP), Vec<(SPP, SP)>>,
+    ) -> Vec<(SPP, SP)> {
+        if spp == self.zero {
+            return vec![];
+        }
+        // Below the last hidden variable, the outputs are unconstrained
+        if hidden.last().is_none_or(|&last| last < level) {
+            return vec![(spp, self.sp.one)];
+        }
+        if let Some(result) = memo.get(&(level, spp)) {
+            return result.clone();
+        }
+        let (x00, x01, x10, x11) = self.cofactors(spp, level);
+        let mut classes: HashMap<SP, SPP> = HashMap::new();
+        if hidden.contains(&level) {
+            // Refine the classes for output 0 and output 1 against each other
+            let x0 = self.union(x00, x10);
+            let x1 = self.union(x01, x11);
+            let mut split0 = self.split_hidden_helper(level + 1, x0, hidden, memo);
+            let mut split1 = self.split_hidden_helper(level + 1, x1, hidden, memo);
+            for split in [&mut split0, &mut split1] {
+                let mut covered = self.zero;
+                for &(guard, _) in split.iter() {
+                    covered = self.union(covered, guard);
+                }
+                let all = self.top_helper(level + 1);
+                let rest = self.difference(all, covered);
+                split.push((rest, self.sp.zero));
+            }
+            for &(guard0, outputs0) in &split0 {
+                for &(guard1, outputs1) in &split1 {
+                    let guard = self.intersect(guard0, guard1);
+                    if guard == self.zero || (outputs0 == self.sp.zero && outputs1 == self.sp.zero) {
+                        continue;
+                    }
+                    let outputs = self.sp.mk(level, outputs0, outputs1);
+                    let guard = self.mk(level, guard, guard, guard, guard);
+                    let entry = classes.entry(outputs).or_insert(self.zero);
+                    *entry = self.union(*entry, guard);
+                }
+            }
+        } else {
+            for (i, x) in [x00, x01, x10, x11].into_iter().enumerate() {
+                for (guard, outputs) in self.split_hidden_helper(level + 1, x, hidden, memo) {
+                    let mut children = [self.zero; 4];
+                    children[i] = guard;
+                    let [g00, g01, g10, g11] = children;
+                    let guard = self.mk(level, g00, g01, g10, g11);
+                    let entry = classes.entry(outputs).or_insert(self.zero);
+                    *entry = self.union(*entry, guard);
                 }
-            } else {
-                res = self.mk(res, zero, zero, res);
             }
-            zero = self.mk(zero, zero, zero, zero);
         }
-        self.test_memo.insert((var, value), res);
-        res
+        let mut result: Vec<(SPP, SP)> = classes.into_iter().map(|(sp, spp)| (spp, sp)).collect();
+        result.sort_by_key(|&(guard, _)| guard);
+        memo.insert((level, spp), result.clone());
+        result
     }
 
-    pub fn assign(&mut self, var: Var, value: bool) -> SPP {
-        if let Some(&result) = self.assign_memo.get(&(var, value)) {
+    pub fn num_vars(&self) -> Var {
+        self.num_vars
+    }
+
+    /// Copies `spp` from another store into this one. The variables keep their indices,
+    /// so this store must have at least as many variables as the variables used by `spp`.
+    /// The variables that `spp` does not use are left unchanged.
+    pub fn import(&mut self, from: &SPPstore, spp: SPP) -> SPP {
+        self.import_helper(from, spp, &mut HashMap::new())
+    }
+
+    fn import_helper(&mut self, from: &SPPstore, spp: SPP, memo: &mut HashMap<SPP, SPP>) -> SPP {
+        if spp == from.zero || spp == from.one {
+            return spp;
+        }
+        if let Some(&result) = memo.get(&spp) {
             return result;
         }
-        let mut res = SPP::new(1);
-        let mut zero = SPP::new(0);
-        for i in (0..self.num_vars).rev() {
-            if i == var {
-                if value {
-                    res = self.mk(zero, res, zero, res);
-                } else {
-                    res = self.mk(res, zero, res, zero);
-                }
-            } else {
-                res = self.mk(res, zero, zero, res);
-            }
-            zero = self.mk(zero, zero, zero, zero);
+        let SPPnode { var, x00, x01, x10, x11 } = from.get(spp);
+        assert!(var < self.num_vars, "variable x{} does not exist in this store", var);
+        let x00 = self.import_helper(from, x00, memo);
+        let x01 = self.import_helper(from, x01, memo);
+        let x10 = self.import_helper(from, x10, memo);
+        let x11 = self.import_helper(from, x11, memo);
+        let res = self.mk(var, x00, x01, x10, x11);
+        memo.insert(spp, res);
+        res
+    }
+
+    /// Tests whether the `width` bits starting at `start` hold `value`.
+    /// The most significant bit of `value` is stored in variable `start`.
+    pub fn test_int(&mut self, start: Var, width: u32, value: u64) -> SPP {
+        // Widths of 64 bits or more would overflow the shifts; their upper bits are zero
+        assert!(value.checked_shr(width).unwrap_or(0) == 0, "value does not fit in the field");
+        let mut res = self.one;
+        for i in 0..width {
+            let bit = value.checked_shr(width - 1 - i).unwrap_or(0) & 1 == 1;
+            let test = self.test(start + i, bit);
+            res = self.sequence(res, test);
+        }
+        res
+    }
+
+    /// Assigns `value` to the `width` bits starting at `start`.
+    /// The most significant bit of `value` is stored in variable `start`.
+    pub fn assign_int(&mut self, start: Var, width: u32, value: u64) -> SPP {
+        assert!(value.checked_shr(width).unwrap_or(0) == 0, "value does not fit in the field");
+        let mut res = self.one;
+        for i in 0..width {
+            let bit = value.checked_shr(width - 1 - i).unwrap_or(0) & 1 == 1;
+            let assign = self.assign(start + i, bit);
+            res = self.sequence(res, assign);
         }
-        self.assign_memo.insert((var, value), res);
         res
     }
 
@@ -485,18 +743,164 @@ impl SPPstore {
         let f10 = self.flip(spp_node.x10);
         let f11 = self.flip(spp_node.x11);
 
-        let res = self.mk(f00, f10, f01, f11);
+        let res = self.mk(spp_node.var, f00, f10, f01, f11);
         self.flip_memo.insert(spp, res);
         res
     }
 
+    /// The number of nodes in the arena
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Mark-and-sweep garbage collection of the SPP arena and the SP store.
+    /// Removes all SPP nodes that are not reachable from `roots` (or from `top`),
+    /// and all SP nodes that are not reachable from `sp_roots`, compacts both arenas
+    /// and purges the memo entries that mention removed nodes.
+    /// Returns the new SPP and SP of each surviving SPP and SP; all others are invalidated.
+    pub fn gc(&mut self, roots: &[SPP], sp_roots: &[SP]) -> (HashMap<SPP, SPP>, HashMap<SP, SP>) {
+        // Mark
+        let mut live = vec![false; self.nodes.len()];
+        let mut stack: Vec<SPP> = roots.to_vec();
+        stack.push(self.top);
+        while let Some(spp) = stack.pop() {
+            if spp.as_u32() < 2 || live[spp.as_usize() - 2] {
+                continue;
+            }
+            live[spp.as_usize() - 2] = true;
+            let SPPnode { x00, x01, x10, x11, .. } = self.get(spp);
+            stack.extend([x00, x01, x10, x11]);
+        }
+
+        // Sweep. Children are always created before their parents,
+        // so their new SPPs are known by the time we reach the parent.
+        let mut remap = HashMap::from([(SPP::new(0), SPP::new(0)), (SPP::new(1), SPP::new(1))]);
+        let old_nodes = std::mem::take(&mut self.nodes);
+        self.hc.clear();
+        for (i, node) in old_nodes.into_iter().enumerate() {
+            if !live[i] {
+                continue;
+            }
+            let node = SPPnode {
+                var: node.var,
+                x00: remap[&node.x00],
+                x01: remap[&node.x01],
+                x10: remap[&node.x10],
+                x11: remap[&node.x11],
+            };
+            let spp = SPP::new(self.nodes.len() as u32 + 2);
+            self.nodes.push(node);
+            self.hc.insert(node, spp);
+            remap.insert(SPP::new(i as u32 + 2), spp);
+        }
+        self.top = remap[&self.top];
+        let sp_remap = self.sp.gc(sp_roots);
+
+        // Purge the memo tables, keeping the entries whose SPPs and SPs all survived
+        let r = |spp: SPP| remap.get(&spp).copied();
+        let r_sp = |sp: SP| sp_remap.get(&sp).copied();
+        for memo in [
+            &mut self.union_memo,
+            &mut self.intersect_memo,
+            &mut self.xor_memo,
+            &mut self.difference_memo,
+            &mut self.sequence_memo,
+        ] {
+            remap_memo(memo, |(a, b), res| Some(((r(a)?, r(b)?), r(res)?)));
+        }
+        for memo in [&mut self.star_memo, &mut self.flip_memo] {
+            remap_memo(memo, |a, res| Some((r(a)?, r(res)?)));
+        }
+        remap_memo(&mut self.complement_memo, |(level, a), res| {
+            Some(((level, r(a)?), r(res)?))
+        });
+        remap_memo(&mut self.fwd_memo, |a, res| Some((r(a)?, r_sp(res)?)));
+        remap_memo(&mut self.ifwd_memo, |(level, a), res| {
+            Some(((level, r_sp(a)?), r(res)?))
+        });
+        (remap, sp_remap)
+    }
+
+    /// Copies the SPPs `roots` and the SPs `sp_roots` into a dump.
+    /// As for SPs, the nodes are numbered in the order in which a depth-first search from the roots finishes them.
+    pub fn export(&self, roots: &[SPP], sp_roots: &[SP]) -> SPPdump {
+        let mut index = HashMap::from([(self.zero, self.zero), (self.one, self.one)]);
+        let mut nodes = vec![];
+        let roots = roots
+            .iter()
+            .map(|&spp| self.export_helper(spp, &mut index, &mut nodes))
+            .collect();
+        SPPdump { num_vars: self.num_vars, nodes, roots, sp: self.sp.export(sp_roots) }
+    }
+
+    fn export_helper(
+        &self,
+        spp: SPP,
+        index: &mut HashMap<SPP, SPP>,
+        nodes: &mut Vec<(Var, SPP, SPP, SPP, SPP)>,
+    ) -> SPP {
+        if let Some(&i) = index.get(&spp) {
+            return i;
+        }
+        let SPPnode { var, x00, x01, x10, x11 } = self.get(spp);
+        let x00 = self.export_helper(x00, index, nodes);
+        let x01 = self.export_helper(x01, index, nodes);
+        let x10 = self.export_helper(x10, index, nodes);
+        let x11 = self.export_helper(x11, index, nodes);
+        let i = SPP::new(nodes.len() as u32 + 2);
+        nodes.push((var, x00, x01, x10, x11));
+        index.insert(spp, i);
+        i
+    }
+
+    /// Adds the nodes of a dump to this store, hash-consing them with the existing nodes,
+    /// and returns the SPPs and SPs of its roots. Fails if the dump needs more variables than the store has,
+    /// or if it is not a valid table of ordered nodes.
+    pub fn load(&mut self, dump: &SPPdump) -> Result<(Vec<SPP>, Vec<SP>), String> {
+        if dump.num_vars > self.num_vars {
+            return Err(format!(
+                "The dump has {} variables, but the store only has {}",
+                dump.num_vars, self.num_vars
+            ));
+        }
+        // The SPP of each node of the dump in this store, and its variable in the dump
+        let mut spps = vec![self.zero, self.one];
+        let mut vars = vec![dump.num_vars, dump.num_vars];
+        for (i, &(var, x00, x01, x10, x11)) in dump.nodes.iter().enumerate() {
+            if var >= dump.num_vars {
+                return Err(format!("Node {} looks at x{}, but the dump has {} variables", i + 2, var, dump.num_vars));
+            }
+            for x in [x00, x01, x10, x11] {
+                if x.as_usize() >= spps.len() {
+                    return Err(format!("Node {} refers to the later node {}", i + 2, x.as_u32()));
+                }
+                if var >= vars[x.as_usize()] {
+                    return Err(format!("Node {} is out of order on x{}", i + 2, var));
+                }
+            }
+            let [x00, x01, x10, x11] = [x00, x01, x10, x11].map(|x| spps[x.as_usize()]);
+            spps.push(self.mk(var, x00, x01, x10, x11));
+            vars.push(var);
+        }
+        let roots = dump
+            .roots
+            .iter()
+            .map(|root| {
+                spps.get(root.as_usize())
+                    .copied()
+                    .ok_or_else(|| format!("Root {} does not exist", root.as_u32()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((roots, self.sp.load(&dump.sp)?))
+    }
+
     /// Enumerates all possible SPPs with `num_vars` fields
     #[cfg(test)]
     pub fn all(&mut self) -> Vec<SPP> {
-        return self.all_helper(self.num_vars);
+        self.all_helper(self.num_vars)
     }
 
-    /// Helper function for `all`: enumerates all SPPs with `depth` fields
+    /// Helper function for `all`: enumerates all SPPs over the last `depth` fields
     #[cfg(test)]
     fn all_helper(&mut self, depth: Var) -> Vec<SPP> {
         if depth == 0 {
@@ -508,7 +912,7 @@ impl SPPstore {
             for &x01 in &all_rec {
                 for &x10 in &all_rec {
                     for &x11 in &all_rec {
-                        result.push(self.mk(x00, x01, x10, x11))
+                        result.push(self.mk(self.num_vars - depth, x00, x01, x10, x11))
                     }
                 }
             }
@@ -522,7 +926,7 @@ impl SPPstore {
         self.rand_helper(self.num_vars)
     }
 
-    /// Helper function for `rand`: generates a random SPP with a certain `depth`
+    /// Helper function for `rand`: generates a random SPP over the last `depth` variables
     #[cfg(test)]
     fn rand_helper(&mut self, depth: Var) -> SPP {
         if depth == 0 {
@@ -536,7 +940,7 @@ impl SPPstore {
         let x01 = self.rand_helper(depth - 1);
         let x10 = self.rand_helper(depth - 1);
         let x11 = self.rand_helper(depth - 1);
-        self.mk(x00, x01, x10, x11)
+        self.mk(self.num_vars - depth, x00, x01, x10, x11)
     }
 
     /// Generates a list containing 100 random SPPs
@@ -615,6 +1019,227 @@ mod tests {
         }
     }
 
+    /// Test that multi-bit tests and assignments agree with their bitwise definitions
+    #[test]
+    fn test_int_fields() {
+        let mut s = SPPstore::new(3);
+        for value in 0..4 {
+            let test = s.test_int(1, 2, value);
+            let assign = s.assign_int(1, 2, value);
+            for input in 0..8u64 {
+                let input_bits: Vec<bool> = (0..3).map(|i| (input >> (2 - i)) & 1 == 1).collect();
+                let output_bits = vec![input_bits[0], value & 2 != 0, value & 1 != 0];
+                // Bits 1..3 of the input hold the field value (most significant bit first)
+                let matches = input & 3 == value;
+                assert_eq!(s.contains(test, &input_bits, &input_bits), matches);
+                assert!(s.contains(assign, &input_bits, &output_bits));
+            }
+            // Assigning a value and then testing for it is the same as assigning it
+            let assign_test = s.sequence(assign, test);
+            assert_eq!(assign_test, assign);
+        }
+        // Fields wider than a value have leading zeros
+        let mut s = SPPstore::new(70);
+        let wide = s.assign_int(0, 70, 1);
+        let mut output = vec![false; 70];
+        output[69] = true;
+        assert!(s.contains(wide, &[true; 70], &output));
+        let test = s.test_int(0, 70, 1);
+        assert_eq!(s.sequence(wide, test), wide);
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut s = SPPstore::new(N);
+        assert_eq!(s.pick(s.zero), None);
+        let assign = s.assign(0, true);
+        assert_eq!(s.pick(assign), Some((vec![false, false], vec![true, false])));
+        for spp in s.some() {
+            if let Some((input, output)) = s.pick(spp) {
+                assert!(s.contains(spp, &input, &output));
+            }
+        }
+    }
+
+    /// Tests that garbage collection preserves the roots and removes everything else
+    #[test]
+    fn test_gc() {
+        let mut s = SPPstore::new(N);
+        let packets: Vec<Vec<bool>> = (0..4)
+            .map(|i: u32| (0..N).map(|j| (i >> (N - 1 - j)) & 1 == 1).collect())
+            .collect();
+        let root = s.rand();
+        let root_sp = s.fwd(root);
+        let contents = |s: &SPPstore, spp: SPP| {
+            let mut pairs = vec![];
+            for input in &packets {
+                for output in &packets {
+                    pairs.push(s.contains(spp, input, output));
+                }
+            }
+            pairs
+        };
+        let expected = contents(&s, root);
+        // Create some garbage
+        for spp in s.some() {
+            let star = s.star(spp);
+            s.sequence(star, root);
+        }
+        let num_nodes = s.num_nodes();
+        let (remap, sp_remap) = s.gc(&[root], &[root_sp]);
+        assert!(s.num_nodes() < num_nodes);
+        let root = remap[&root];
+        assert_eq!(contents(&s, root), expected);
+        assert_eq!(s.fwd(root), sp_remap[&root_sp]);
+        // Hash consing and the operations still work after compaction
+        let flipped = s.flip(root);
+        assert_eq!(s.flip(flipped), root);
+        assert_eq!(s.sequence(s.one, root), root);
+        assert_eq!(s.union(root, s.top), s.top);
+        let test = s.test(0, true);
+        assert_eq!(s.sequence(test, test), test);
+    }
+
+    /// Tests that a dump reloads to the same SPPs and SPs, also in a store with a different history
+    #[test]
+    fn test_dump() {
+        let mut s = SPPstore::new(N);
+        let roots = s.some();
+        let sp_roots: Vec<SP> = roots.iter().map(|&spp| s.fwd(spp)).collect();
+        let dump = s.export(&roots, &sp_roots);
+        let json = serde_json::to_string(&dump).unwrap();
+        let dump: SPPdump = serde_json::from_str(&json).unwrap();
+        assert_eq!(s.load(&dump), Ok((roots.clone(), sp_roots.clone())));
+        let mut t = SPPstore::new(N);
+        for spp in t.some() {
+            t.star(spp);
+        }
+        let (loaded, sp_loaded) = t.load(&dump).unwrap();
+        assert_eq!(t.export(&loaded, &sp_loaded), dump);
+        // The operations agree on the reloaded SPPs
+        for (&spp, &loaded) in roots.iter().zip(&loaded) {
+            let star = s.star(spp);
+            let expected = s.export(&[star], &[]);
+            let star = t.star(loaded);
+            assert_eq!(t.export(&[star], &[]), expected);
+        }
+        let missing = SPPdump { roots: vec![SPP(dump.nodes.len() as u32 + 2)], ..dump };
+        assert!(t.load(&missing).is_err());
+    }
+
+    /// Tests the operations on the reduced representation against their definitions on concrete packets
+    #[test]
+    fn test_semantics() {
+        const M: Var = 3;
+        let mut s = SPPstore::new(M);
+        let packets: Vec<Vec<bool>> = (0..8)
+            .map(|i: u32| (0..M).map(|j| (i >> (M - 1 - j)) & 1 == 1).collect())
+            .collect();
+        let rel = |s: &SPPstore, spp: SPP| -> Vec<Vec<bool>> {
+            packets
+                .iter()
+                .map(|p| packets.iter().map(|q| s.contains(spp, p, q)).collect())
+                .collect()
+        };
+        let samples = s.some();
+        for pair in samples.chunks(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let (ra, rb) = (rel(&s, a), rel(&s, b));
+            let union = s.union(a, b);
+            let intersect = s.intersect(a, b);
+            let xor = s.xor(a, b);
+            let difference = s.difference(a, b);
+            let complement = s.complement(a);
+            let sequence = s.sequence(a, b);
+            let flip = s.flip(a);
+            let star = s.star(a);
+            let (r_union, r_intersect, r_xor) = (rel(&s, union), rel(&s, intersect), rel(&s, xor));
+            let (r_difference, r_complement) = (rel(&s, difference), rel(&s, complement));
+            let (r_sequence, r_flip, r_star) = (rel(&s, sequence), rel(&s, flip), rel(&s, star));
+            // The reflexive transitive closure of `a`
+            let mut closure: Vec<Vec<bool>> = (0..8).map(|i| (0..8).map(|j| i == j).collect()).collect();
+            for k in 0..8 {
+                for i in 0..8 {
+                    for j in 0..8 {
+                        closure[i][j] |= (closure[i][k] || ra[i][k]) && (closure[k][j] || ra[k][j]);
+                    }
+                }
+            }
+            for i in 0..8 {
+                for j in 0..8 {
+                    assert_eq!(r_union[i][j], ra[i][j] || rb[i][j]);
+                    assert_eq!(r_intersect[i][j], ra[i][j] && rb[i][j]);
+                    assert_eq!(r_xor[i][j], ra[i][j] != rb[i][j]);
+                    assert_eq!(r_difference[i][j], ra[i][j] && !rb[i][j]);
+                    assert_eq!(r_complement[i][j], !ra[i][j]);
+                    assert_eq!(r_sequence[i][j], (0..8).any(|k| ra[i][k] && rb[k][j]));
+                    assert_eq!(r_flip[i][j], ra[j][i]);
+                    assert_eq!(r_star[i][j], closure[i][j]);
+                }
+            }
+            let outputs = s.fwd(a);
+            for (j, q) in packets.iter().enumerate() {
+                assert_eq!(s.sp.contains(outputs, q), (0..8).any(|i| ra[i][j]));
+            }
+        }
+    }
+
+    /// Checks `exists` and `split_hidden` against their definitions, hiding the middle variable
+    #[test]
+    fn test_projection() {
+        const M: Var = 3;
+        let hidden = [1];
+        let mut s = SPPstore::new(M);
+        let packets: Vec<Vec<bool>> = (0..8)
+            .map(|i: u32| (0..M).map(|j| (i >> (M - 1 - j)) & 1 == 1).collect())
+            .collect();
+        let similar = |p: &Vec<bool>, q: &Vec<bool>| p[0] == q[0] && p[2] == q[2];
+        for a in s.some() {
+            let exists = s.exists(a, &hidden);
+            let split = s.split_hidden(a, &hidden);
+            for p in &packets {
+                for q in &packets {
+                    // The outputs similar to q that some input similar to p produces
+                    let outputs: Vec<&Vec<bool>> = packets
+                        .iter()
+                        .filter(|q2| similar(q, q2))
+                        .filter(|q2| packets.iter().any(|p2| similar(p, p2) && s.contains(a, p2, q2)))
+                        .collect();
+                    assert_eq!(s.contains(exists, p, q), !outputs.is_empty());
+                    let classes: Vec<&(SPP, SP)> =
+                        split.iter().filter(|(guard, _)| s.contains(*guard, p, q)).collect();
+                    if outputs.is_empty() {
+                        assert!(classes.is_empty());
+                        continue;
+                    }
+                    assert_eq!(classes.len(), 1);
+                    for q2 in packets.iter().filter(|q2| similar(q, q2)) {
+                        assert_eq!(s.sp.contains(classes[0].1, q2), outputs.contains(&q2));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tests that skipped levels keep the representation small
+    #[test]
+    fn test_reduced_size() {
+        let mut s = SPPstore::new(64);
+        let num_nodes = s.num_nodes();
+        assert_eq!(num_nodes, 64, "Only top needs a node for every variable");
+        let test = s.test(10, true);
+        let assign = s.assign(20, false);
+        assert_eq!(s.num_nodes(), num_nodes + 2);
+        let seq = s.sequence(test, assign);
+        assert_eq!(s.get(seq).var, 10);
+        assert_eq!(s.get(s.get(seq).x11).var, 20);
+        let field = s.test_int(32, 32, 0xdeadbeef);
+        assert_eq!(s.var(field), 32);
+        let sp = s.fwd(field);
+        assert_eq!(s.sp.var(sp), 32);
+        assert!(s.sp.num_nodes() <= 32);
+    }
+
     #[test]
     fn test_laws_0() {
         let mut s = SPPstore::new(N);
@@ -0,0 +1,124 @@
+// Concrete traces, used as counterexamples for emptiness and equivalence checks.
+// Finite strings are `Trace`s, infinite strings (omega mode) are `Lasso`s.
+
+use crate::fields::Fields;
+use crate::pre::Value;
+use std::fmt;
+
+/// A concrete packet: the value of each field x0, x1, ... xk
+pub type Packet = Vec<Value>;
+
+/// A concrete string of the language of an automaton.
+/// A string `pk0 pk1 ... pkn out` consists of the packets recorded along the way
+/// (one per transition) followed by the output packet (produced by epsilon).
+/// The `packets` vector therefore always contains at least two packets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub packets: Vec<Packet>,
+}
+
+impl Trace {
+    pub fn new(packets: Vec<Packet>) -> Self {
+        assert!(packets.len() >= 2, "A trace has an input and an output packet");
+        Trace { packets }
+    }
+
+    /// The number of transitions (dup steps) taken by the trace
+    pub fn num_steps(&self) -> usize {
+        self.packets.len() - 2
+    }
+
+    /// Displays the trace with its packets formatted according to a field layout
+    pub fn display<'a>(&'a self, fields: &'a Fields) -> TraceDisplay<'a> {
+        TraceDisplay {
+            trace: self,
+            fields,
+        }
+    }
+}
+
+/// Formats a packet as `x0=1,x1=0`
+pub fn packet_to_string(packet: &[Value]) -> String {
+    Fields::new().format_packet(packet)
+}
+
+/// Helper for displaying a trace with named fields, see `Trace::display`
+pub struct TraceDisplay<'a> {
+    trace: &'a Trace,
+    fields: &'a Fields,
+}
+
+impl fmt::Display for TraceDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packets: Vec<String> = self
+            .trace
+            .packets
+            .iter()
+            .map(|p| self.fields.format_packet(p))
+            .collect();
+        write!(f, "{}", packets.join(" -> "))
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(&Fields::new()).fmt(f)
+    }
+}
+
+/// A concrete infinite string of the omega language of an automaton, in lasso form:
+/// the packets of `prefix` followed by the packets of `cycle` repeated forever.
+/// The `cycle` is never empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lasso {
+    pub prefix: Vec<Packet>,
+    pub cycle: Vec<Packet>,
+}
+
+impl Lasso {
+    pub fn new(prefix: Vec<Packet>, cycle: Vec<Packet>) -> Self {
+        assert!(!cycle.is_empty(), "A lasso has a non-empty cycle");
+        Lasso { prefix, cycle }
+    }
+
+    /// The `i`-th packet of the infinite string
+    pub fn packet(&self, i: usize) -> &Packet {
+        if i < self.prefix.len() {
+            &self.prefix[i]
+        } else {
+            &self.cycle[(i - self.prefix.len()) % self.cycle.len()]
+        }
+    }
+
+    /// Displays the lasso with its packets formatted according to a field layout
+    pub fn display<'a>(&'a self, fields: &'a Fields) -> LassoDisplay<'a> {
+        LassoDisplay {
+            lasso: self,
+            fields,
+        }
+    }
+}
+
+/// Helper for displaying a lasso with named fields, see `Lasso::display`
+pub struct LassoDisplay<'a> {
+    lasso: &'a Lasso,
+    fields: &'a Fields,
+}
+
+impl fmt::Display for LassoDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = |packets: &[Packet]| -> Vec<String> {
+            packets.iter().map(|p| self.fields.format_packet(p)).collect()
+        };
+        for packet in format(&self.lasso.prefix) {
+            write!(f, "{} -> ", packet)?;
+        }
+        write!(f, "({})^ω", format(&self.lasso.cycle).join(" -> "))
+    }
+}
+
+impl fmt::Display for Lasso {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(&Fields::new()).fmt(f)
+    }
+}
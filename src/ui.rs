@@ -21,6 +21,9 @@ struct AppState {
 #[derive(Deserialize)]
 struct ExpressionRequest {
     expression: String,
+    // Show the pruned automaton instead of the full one
+    #[serde(default)]
+    prune: bool,
 }
 
 // Response structure for expression evaluation
@@ -29,6 +32,8 @@ struct ExpressionResponse {
     success: bool,
     message: String,
     report_url: Option<String>,
+    // Shortest string accepted by the expression (None if it is empty)
+    witness: Option<String>,
     error: Option<String>,
 }
 
@@ -166,29 +171,44 @@ async fn evaluate_expression(
         success: false,
         message: String::new(),
         report_url: None,
+        witness: None,
         error: None,
     };
 
     // Parse and evaluate the expression
-    match parser::parse_expressions(expression) {
-        Ok(expressions) => {
-            if expressions.is_empty() {
-                response.error = Some("No valid expressions found".to_string());
-                return Ok(HttpResponse::Ok().json(response));
-            }
-
+    match parser::parse_program(expression) {
+        Ok(program) => {
             // Use the first expression
-            let expr = &expressions[0];
+            let expr = match program.stmts.first() {
+                Some(parser::Stmt::Expr(expr)) => expr,
+                Some(parser::Stmt::Check(..)) => {
+                    response.error = Some("Error parsing expression: Unexpected check statement".to_string());
+                    return Ok(HttpResponse::Ok().json(response));
+                }
+                None => {
+                    response.error = Some("No valid expressions found".to_string());
+                    return Ok(HttpResponse::Ok().json(response));
+                }
+            };
 
             // Get a unique output directory for this evaluation
             let output_dir = create_output_dir(&data.temp_dir)?;
 
             // Create automaton with the correct number of variables
-            let mut aut = Aut::new(expr.num_fields());
+            let mut aut = Aut::new(expr.num_fields().max(program.fields.num_bits()));
             let state = aut.expr_to_state(expr);
+            response.witness = aut
+                .shortest_witness(state)
+                .map(|trace| trace.display(&program.fields).to_string());
+            aut.set_fields(program.fields);
 
             // Generate visualization
-            match viz::render_aut(state, &mut aut, &output_dir) {
+            let rendered = if request.prune {
+                viz::render_pruned_aut(state, &mut aut, &output_dir)
+            } else {
+                viz::render_aut(state, &mut aut, &output_dir)
+            };
+            match rendered {
                 Ok(_) => {
                     // Determine the relative URL for the visualization report
                     let dir_name = output_dir.file_name().unwrap().to_string_lossy();
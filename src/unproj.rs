@@ -0,0 +1,148 @@
+// Un-projection: compiles the automaton state into extra packet fields.
+//
+// The states of an explicit automaton are numbered 0..n, so they fit in ceil(log2 n) bits.
+// We widen the packet with these bits (placed after the packet variables) and turn the
+// automaton into two dup-free relations on the widened packets: `step`, which takes one dup
+// step (state==s; A_st; state:=t for every transition s -> t), and `accept`, which produces
+// the output packet (state==s; E_s for every state s). The input/output relation of the
+// automaton (Phi) is then `state:=0; step*; accept`, with the state bits forgotten.
+
+use crate::aut::ExplicitAut;
+use crate::spp::{SPP, SPPstore, Var};
+
+/// An automaton whose state is stored in the packet, see the module documentation
+#[derive(Debug)]
+pub struct Unprojection {
+    /// The widened store; `step` and `accept` live in this store
+    pub store: SPPstore,
+    /// The first state bit, i.e. the number of packet variables
+    pub start: Var,
+    /// The number of state bits; the state number is stored most significant bit first
+    pub bits: u32,
+    pub step: SPP,
+    pub accept: SPP,
+}
+
+impl Unprojection {
+    /// Tests whether the state bits hold `state`
+    pub fn test_state(&mut self, state: usize) -> SPP {
+        self.store.test_int(self.start, self.bits, state as u64)
+    }
+
+    /// Sets the state bits to `state`
+    pub fn assign_state(&mut self, state: usize) -> SPP {
+        self.store.assign_int(self.start, self.bits, state as u64)
+    }
+
+    /// The state variables, e.g. to quantify them away with `SPPstore::exists`
+    pub fn state_vars(&self) -> Vec<Var> {
+        (self.start..self.start + self.bits).collect()
+    }
+
+    /// The dup-free relation of the whole automaton on the widened packets:
+    /// starts in the root, takes any number of steps and accepts
+    pub fn relation(&mut self) -> SPP {
+        let init = self.assign_state(0);
+        let steps = self.store.star(self.step);
+        let run = self.store.sequence(init, steps);
+        self.store.sequence(run, self.accept)
+    }
+}
+
+/// The number of bits needed to number `num_states` states
+fn state_bits(num_states: usize) -> u32 {
+    num_states.max(1).next_power_of_two().trailing_zeros()
+}
+
+/// Un-projects an explicit automaton whose SPPs live in `from`
+pub fn unproject(explicit: &ExplicitAut, from: &SPPstore) -> Unprojection {
+    let start = from.num_vars();
+    let bits = state_bits(explicit.num_states());
+    let mut unproj = Unprojection {
+        store: SPPstore::new(start + bits),
+        start,
+        bits,
+        step: SPP::new(0),
+        accept: SPP::new(0),
+    };
+    let mut step = unproj.store.zero;
+    let mut accept = unproj.store.zero;
+    for state in 0..explicit.num_states() {
+        let test = unproj.test_state(state);
+        let epsilon = unproj.store.import(from, explicit.epsilons[state]);
+        let epsilon = unproj.store.sequence(test, epsilon);
+        accept = unproj.store.union(accept, epsilon);
+        for (&target, &spp) in explicit.transitions[state].get_transitions() {
+            let spp = unproj.store.import(from, spp);
+            let assign = unproj.assign_state(target);
+            let spp = unproj.store.sequence(test, spp);
+            let spp = unproj.store.sequence(spp, assign);
+            step = unproj.store.union(step, spp);
+        }
+    }
+    unproj.step = step;
+    unproj.accept = accept;
+    unproj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::state_bits;
+    use crate::aut::Aut;
+
+    #[test]
+    fn test_state_bits() {
+        for (num_states, bits) in [(0, 0), (1, 0), (2, 1), (3, 2), (4, 2), (5, 3), (9, 4)] {
+            assert_eq!(state_bits(num_states), bits, "{}", num_states);
+        }
+    }
+
+    #[test]
+    fn test_unproject_matches_eliminate() {
+        let mut aut = Aut::new(2);
+        for input in [
+            "0",
+            "dup",
+            "x0==1; dup; x1:=1",
+            "(x0:=1; dup; x1:=1)*",
+            "((x0==0; x0:=1; dup) + (x0==1; x0:=0; dup))*; x1:=1",
+            "(x0:=1; dup)* ; (x1==0; dup; x1:=1)*",
+            "!(dup; dup) & X X (x1==1)",
+            "(x0==0; T) U end",
+        ] {
+            let state = aut.parse_state(input);
+            let num_states = aut.explore(state).num_states();
+            let mut unproj = aut.unproject(state);
+            assert_eq!(unproj.bits, state_bits(num_states), "{}", input);
+            assert_eq!(unproj.store.num_vars(), 2 + unproj.bits, "{}", input);
+
+            // Forgetting the state bits gives back the dup-free relation
+            let hidden = unproj.state_vars();
+            let relation = unproj.relation();
+            let relation = unproj.store.exists(relation, &hidden);
+            let eliminated = aut.eliminate(state);
+            let eliminated = unproj.store.import(aut.spp_store(), eliminated);
+            let eliminated = unproj.store.exists(eliminated, &hidden);
+            assert_eq!(relation, eliminated, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_unproject_step() {
+        // x0:=1; dup; x1:=1 has two states: before and after the dup
+        let mut aut = Aut::new(2);
+        let state = aut.parse_state("x0:=1; dup; x1:=1");
+        let mut unproj = aut.unproject(state);
+        assert_eq!(unproj.bits, 1);
+        let s0 = unproj.test_state(0);
+        let to_s1 = unproj.store.assign(0, true);
+        let s1 = unproj.assign_state(1);
+        let step = unproj.store.sequence(s0, to_s1);
+        let step = unproj.store.sequence(step, s1);
+        assert_eq!(unproj.step, step);
+        let s1 = unproj.test_state(1);
+        let x1 = unproj.store.assign(1, true);
+        let accept = unproj.store.sequence(s1, x1);
+        assert_eq!(unproj.accept, accept);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::aut::Aut;
+use crate::aut::{Aut, ExplicitAut};
 use crate::spp::{SPP, SPPstore};
 use regex;
 use std::collections::{HashMap, HashSet};
@@ -238,43 +238,39 @@ pub fn render_spp(index: SPP, store: &SPPstore, output_dir: &Path) -> Result<()>
 /// 4. Creates a graphviz representation of the automaton
 /// 5. Generates an HTML report that includes all visualizations
 pub fn render_aut(root_state: usize, aut: &mut Aut, output_dir: &Path) -> Result<()> {
+    let explicit = aut.explore(root_state);
+    render_explicit_aut(&explicit, aut, output_dir)
+}
+
+/// Like `render_aut`, but shows the pruned automaton, in which every SPP only contains
+/// the packet pairs that occur in some accepted string
+pub fn render_pruned_aut(root_state: usize, aut: &mut Aut, output_dir: &Path) -> Result<()> {
+    let explicit = aut.prune(root_state);
+    render_explicit_aut(&explicit, aut, output_dir)
+}
+
+/// Renders an explored automaton of `aut` into visualizations and an HTML report,
+/// showing the `Aut` state of each explicit state
+pub fn render_explicit_aut(explicit: &ExplicitAut, aut: &mut Aut, output_dir: &Path) -> Result<()> {
     // Ensure the output directory exists
     fs::create_dir_all(output_dir)?;
 
-    // Set up tracking structures
-    let mut visited_states = HashSet::new();
-    let mut states_to_process = vec![root_state];
+    let visited_states: HashSet<usize> = explicit.states.iter().copied().collect();
     let mut transitions = Vec::new();
     let mut spp_ids = HashSet::new();
     let mut state_expressions = HashMap::new();
-
-    // Explore the automaton
-    while let Some(state) = states_to_process.pop() {
-        if !visited_states.insert(state) {
-            continue; // Skip if already visited
-        }
-
-        // Get expression string for this state
-        let expr_string = aut.state_to_string(state);
-        state_expressions.insert(state, expr_string);
-
-        // Get epsilon for this state
-        let epsilon_spp = aut.epsilon(state);
-        spp_ids.insert(epsilon_spp);
+    let mut state_epsilons = HashMap::new();
+    for (i, &state) in explicit.states.iter().enumerate() {
+        state_expressions.insert(state, aut.state_to_string(state));
+        state_epsilons.insert(state, explicit.epsilons[i]);
+        spp_ids.insert(explicit.epsilons[i]);
 
         // Collect SPPs from the expression
         aut.collect_spps(state, &mut spp_ids);
 
-        // Get transitions (delta) for this state
-        let delta = aut.delta(state);
-        for (&target_state, &spp) in delta.get_transitions() {
-            transitions.push((state, target_state, spp));
+        for (&target, &spp) in explicit.transitions[i].get_transitions() {
+            transitions.push((state, explicit.states[target], spp));
             spp_ids.insert(spp);
-
-            // Add target state to processing queue if not already visited
-            if !visited_states.contains(&target_state) {
-                states_to_process.push(target_state);
-            }
         }
     }
 
@@ -291,7 +287,7 @@ pub fn render_aut(root_state: usize, aut: &mut Aut, output_dir: &Path) -> Result
 
     // Add nodes with clickable SPP references
     for state in &visited_states {
-        let epsilon_spp = aut.epsilon(*state);
+        let epsilon_spp = state_epsilons[state];
         let unknown = String::from("Unknown");
         let expr = state_expressions.get(state).unwrap_or(&unknown);
 
@@ -579,7 +575,45 @@ pub fn render_aut(root_state: usize, aut: &mut Aut, output_dir: &Path) -> Result
             <div id="tooltip" class="tooltip"></div>
         </div>
     </div>
-    
+"#,
+    );
+
+    // Add the field legend, mapping the declared field names to SPP levels
+    if !aut.fields().is_empty() {
+        html_content.push_str(
+            r#"    
+    <div class="section">
+        <h2>Fields</h2>
+        <table id="fields-table">
+            <thead>
+                <tr>
+                    <th>Field</th>
+                    <th>Width</th>
+                    <th>SPP Levels</th>
+                </tr>
+            </thead>
+            <tbody>
+"#,
+        );
+        for decl in aut.fields().decls() {
+            html_content.push_str(&format!(
+                "                <tr>\n                    <td>{}</td>\n                    <td>{}</td>\n                    <td>x{}..x{}</td>\n                </tr>\n",
+                html_escape(&decl.name),
+                decl.width,
+                decl.start,
+                decl.start + decl.width - 1
+            ));
+        }
+        html_content.push_str(
+            r#"            </tbody>
+        </table>
+    </div>
+"#,
+        );
+    }
+
+    html_content.push_str(
+        r#"    
     <div class="section">
         <h2>SPP Visualizations</h2>
         <div class="flex-container" id="spp-container">
@@ -633,7 +667,7 @@ pub fn render_aut(root_state: usize, aut: &mut Aut, output_dir: &Path) -> Result
     let mut state_vec: Vec<_> = visited_states.iter().collect();
     state_vec.sort();
     for state in state_vec {
-        let epsilon_spp = aut.epsilon(*state);
+        let epsilon_spp = state_epsilons[state];
         let unknown = String::from("Unknown");
         let expr_string = state_expressions.get(state).unwrap_or(&unknown);
 
@@ -1,7 +1,9 @@
 document.addEventListener('DOMContentLoaded', function() {
     const editor = document.getElementById('expression-editor');
     const evaluateBtn = document.getElementById('evaluate-btn');
+    const pruneCheckbox = document.getElementById('prune-checkbox');
     const errorDisplay = document.getElementById('error-display');
+    const witnessDisplay = document.getElementById('witness-display');
     const visualizationContainer = document.getElementById('visualization-container');
     const placeholder = document.querySelector('.placeholder');
     const visualizationFrame = document.getElementById('visualization-frame');
@@ -16,6 +18,7 @@ document.addEventListener('DOMContentLoaded', function() {
         // Don't evaluate if empty
         if (!expression) {
             errorDisplay.style.display = 'none';
+            witnessDisplay.style.display = 'none';
             visualizationFrame.style.display = 'none';
             placeholder.style.display = 'flex';
             return;
@@ -27,7 +30,7 @@ document.addEventListener('DOMContentLoaded', function() {
                 headers: {
                     'Content-Type': 'application/json',
                 },
-                body: JSON.stringify({ expression }),
+                body: JSON.stringify({ expression, prune: pruneCheckbox.checked }),
             });
             
             const result = await response.json();
@@ -40,10 +43,17 @@ document.addEventListener('DOMContentLoaded', function() {
                 
                 // Set the iframe source to the generated report
                 visualizationFrame.src = result.report_url;
+
+                // Show the shortest accepted trace, if any
+                witnessDisplay.style.display = 'block';
+                witnessDisplay.textContent = result.witness
+                    ? 'Shortest trace: ' + result.witness
+                    : 'The expression is empty';
             } else {
                 // Show error message
                 errorDisplay.style.display = 'block';
                 errorDisplay.textContent = result.error || 'An unknown error occurred';
+                witnessDisplay.style.display = 'none';
                 
                 // Hide visualization if there was an error
                 visualizationFrame.style.display = 'none';
@@ -58,6 +68,7 @@ document.addEventListener('DOMContentLoaded', function() {
     
     // Event listener for the evaluate button
     evaluateBtn.addEventListener('click', evaluateExpression);
+    pruneCheckbox.addEventListener('change', evaluateExpression);
     
     // Evaluate on typing after delay
     editor.addEventListener('keyup', function() {